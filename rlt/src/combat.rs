@@ -0,0 +1,246 @@
+use super::*;
+
+// An attack hits if a d20 plus the attacker's power reaches the target's evasion, which is
+// BASE_EVASION plus its defense. A natural 20 always hits and is a critical hit, which rolls the
+// damage dice twice, and a natural 1 always misses
+const ATTACK_DIE: i32 = 20;
+const BASE_EVASION: i32 = 10;
+const CRITICAL_ROLL: i32 = 20;
+const FUMBLE_ROLL: i32 = 1;
+
+// A number of dice with the same number of sides, such as 1d10
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+}
+
+// How an attack went, with every roll that went into it. damage is what the target loses after
+// its armour absorbed its share, and is 0 on a miss
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackRoll {
+    pub roll: i32,
+    pub power: i32,
+    pub evasion: i32,
+    pub hit: bool,
+    pub critical: bool,
+    pub dice: Dice,
+    pub damage_rolls: Vec<i32>,
+    pub absorbed: i32,
+    pub damage: i32,
+}
+
+impl Dice {
+    /// Returns the damage dice for an attack power: one die with twice as many sides as the power,
+    /// so that an average roll is just over the power
+    pub fn for_power(power: i32) -> Self {
+        Dice {
+            count: 1,
+            sides: cmp::max(2 * power, 1),
+        }
+    }
+
+    /// Rolls each die and returns the rolls
+    pub fn roll(self, rng: &mut GameRng) -> Vec<i32> {
+        (0..self.count)
+            .map(|_| rng.gen_range(1, self.sides + 1))
+            .collect()
+    }
+
+    /// Returns how likely each total is, indexed by the total
+    fn distribution(self) -> Vec<f32> {
+        let mut chances = vec![1.0];
+        for _ in 0..self.count {
+            let mut next = vec![0.0; chances.len() + self.sides as usize];
+            for (total, chance) in chances.iter().enumerate() {
+                for side in 1..=self.sides as usize {
+                    next[total + side] += chance / self.sides as f32;
+                }
+            }
+            chances = next;
+        }
+        chances
+    }
+}
+
+impl std::fmt::Display for Dice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)
+    }
+}
+
+impl std::fmt::Display for AttackRoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "rolls {}+{} vs {}", self.roll, self.power, self.evasion)?;
+        if !self.hit {
+            return write!(f, " and misses");
+        }
+        let rolls: Vec<String> = self.damage_rolls.iter().map(i32::to_string).collect();
+        write!(
+            f,
+            ", {} for {} hit points ({}: {}, armour absorbs {})",
+            if self.critical {
+                "critical hit"
+            } else {
+                "hits"
+            },
+            self.damage,
+            self.dice,
+            rolls.join("+"),
+            self.absorbed
+        )
+    }
+}
+
+/// Returns the evasion an attack has to reach to hit a target with the defense
+///
+fn evasion(defense: i32) -> i32 {
+    BASE_EVASION + defense
+}
+
+/// Returns how much of each hit a target's armour absorbs: half its defense
+///
+fn absorbed(defense: i32) -> i32 {
+    cmp::max(defense, 0) / 2
+}
+
+/// Returns whether the natural roll of the attack die hits, and whether the hit is critical
+///
+fn hits(roll: i32, power: i32, defense: i32) -> (bool, bool) {
+    match roll {
+        CRITICAL_ROLL => (true, true),
+        FUMBLE_ROLL => (false, false),
+        _ => (roll + power >= evasion(defense), false),
+    }
+}
+
+/// Rolls an attack with the power against a target with the defense. A hit always does at least
+/// 1 damage, however well armoured the target is
+///
+pub fn roll_attack(power: i32, defense: i32, rng: &mut GameRng) -> AttackRoll {
+    let roll = rng.gen_range(1, ATTACK_DIE + 1);
+    let (hit, critical) = hits(roll, power, defense);
+    let mut dice = Dice::for_power(power);
+    if critical {
+        dice.count *= 2;
+    }
+    let damage_rolls = if hit { dice.roll(rng) } else { vec![] };
+    let absorbed = if hit { absorbed(defense) } else { 0 };
+    let damage = if hit {
+        cmp::max(damage_rolls.iter().sum::<i32>() - absorbed, 1)
+    } else {
+        0
+    };
+    AttackRoll {
+        roll,
+        power,
+        evasion: evasion(defense),
+        hit,
+        critical,
+        dice,
+        damage_rolls,
+        absorbed,
+        damage,
+    }
+}
+
+/// Returns the damage an attack with the power does to a target with the defense on average,
+/// misses and critical hits included
+///
+pub fn expected_damage(power: i32, defense: i32) -> f32 {
+    let average_hit = |dice: Dice| -> f32 {
+        dice.distribution()
+            .iter()
+            .enumerate()
+            .map(|(total, chance)| chance * cmp::max(total as i32 - absorbed(defense), 1) as f32)
+            .sum()
+    };
+    let dice = Dice::for_power(power);
+    let critical_dice = Dice {
+        count: dice.count * 2,
+        ..dice
+    };
+    let total: f32 = (1..=ATTACK_DIE)
+        .map(|roll| match hits(roll, power, defense) {
+            (true, true) => average_hit(critical_dice),
+            (true, false) => average_hit(dice),
+            (false, _) => 0.0,
+        })
+        .sum();
+    total / ATTACK_DIE as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 3;
+
+    /// Rolls attacks until one has the natural roll and returns it
+    fn attack_with_roll(roll: i32, power: i32, defense: i32, rng: &mut GameRng) -> AttackRoll {
+        (0..10_000)
+            .map(|_| roll_attack(power, defense, rng))
+            .find(|attack| attack.roll == roll)
+            .unwrap()
+    }
+
+    #[test]
+    fn natural_20_is_a_critical_hit_with_double_dice() {
+        let mut rng = GameRng::new(SEED);
+        let attack = attack_with_roll(CRITICAL_ROLL, 1, 50, &mut rng);
+        assert!(attack.hit && attack.critical);
+        assert_eq!(attack.dice, Dice { count: 2, sides: 2 });
+        assert_eq!(attack.damage_rolls.len(), 2);
+    }
+
+    #[test]
+    fn natural_1_always_misses() {
+        let mut rng = GameRng::new(SEED);
+        let attack = attack_with_roll(FUMBLE_ROLL, 100, 0, &mut rng);
+        assert!(!attack.hit && !attack.critical);
+        assert!(attack.damage_rolls.is_empty());
+        assert_eq!(attack.damage, 0);
+    }
+
+    #[test]
+    fn armour_absorbs_half_the_defense() {
+        let mut rng = GameRng::new(SEED);
+        for _ in 0..100 {
+            let attack = roll_attack(20, 7, &mut rng);
+            if attack.hit {
+                assert_eq!(attack.absorbed, 3);
+                let rolled: i32 = attack.damage_rolls.iter().sum();
+                assert_eq!(attack.damage, cmp::max(rolled - 3, 1));
+            }
+        }
+    }
+
+    #[test]
+    fn hit_does_at_least_1_damage() {
+        let mut rng = GameRng::new(SEED);
+        let hits: Vec<AttackRoll> = (0..1000)
+            .map(|_| roll_attack(1, 40, &mut rng))
+            .filter(|attack| attack.hit)
+            .collect();
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|attack| attack.damage == 1));
+    }
+
+    #[test]
+    fn dice_distribution_sums_the_dice() {
+        let chances = Dice { count: 2, sides: 6 }.distribution();
+        assert_eq!(chances.len(), 13);
+        assert_eq!(chances[0..2], [0.0, 0.0]);
+        assert!((chances[7] - 6.0 / 36.0).abs() < 1e-6);
+        assert!((chances.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn expected_damage_counts_misses_and_critical_hits() {
+        // Against evasion 10, power 1 hits on 9 to 19 with 1d2 for 1.5 on average, and on a 20
+        // with 2d2 for 3: (11 * 1.5 + 3) / 20
+        assert!((expected_damage(1, 0) - 0.975).abs() < 1e-6);
+        // only the critical hits land, and each one does the minimum of 1
+        assert!((expected_damage(1, 40) - 0.05).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,246 @@
+use super::mapgen::{flood_fill, in_bounds};
+use super::*;
+
+// The chance out of 100 that a door is locked, and the most locked doors a level can have
+const LOCKED_DOOR_CHANCE: u32 = 25;
+const MAX_LOCKED_DOORS: usize = 2;
+
+// A door in a room's entrance. A closed door blocks movement and sight like a wall. Anyone can
+// open a door that isn't locked by walking into it, a locked one takes a key that is used up
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Door {
+    pub open: bool,
+    pub locked: bool,
+}
+
+/// Returns the entrances of a room: the gaps cut into its walls by corridors, where a door fits
+/// between two walls
+///
+fn entrances(map: &Map, room: &Room) -> Vec<(i32, i32)> {
+    let blocked = |x: i32, y: i32| map[x as usize][y as usize].blocked;
+    let walls = (room.x1..=room.x2)
+        .flat_map(|x| vec![(x, room.y1), (x, room.y2)])
+        .chain(((room.y1 + 1)..room.y2).flat_map(|y| vec![(room.x1, y), (room.x2, y)]));
+    walls
+        .filter(|&(x, y)| {
+            in_bounds(x, y)
+                && !blocked(x, y)
+                && ((blocked(x - 1, y) && blocked(x + 1, y))
+                    || (blocked(x, y - 1) && blocked(x, y + 1)))
+        })
+        .collect()
+}
+
+/// Puts closed doors in the entrances of the rooms, and locks a few of them. The key for each
+/// locked door is dropped on a free tile that can be reached from the start without going through
+/// a locked door, and a door that would shut away a key already dropped is left unlocked. Without
+/// a key item in the raws no door is locked
+///
+pub fn place_doors(
+    map: &mut Map,
+    rooms: &[Room],
+    objects: &mut Vec<Object>,
+    key: Option<&ItemRaw>,
+    rng: &mut GameRng,
+) {
+    let mut doors: Vec<(i32, i32)> = vec![];
+    for room in rooms {
+        for (x, y) in entrances(map, room) {
+            let tile = &map[x as usize][y as usize];
+            if !doors.contains(&(x, y))
+                && !tile.stairs
+                && tile.portal.is_none()
+                && !objects.iter().any(|object| object.pos() == (x, y))
+            {
+                doors.push((x, y));
+            }
+        }
+    }
+    for &(x, y) in &doors {
+        map[x as usize][y as usize] = Tile::door(false);
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => return,
+    };
+    let start = objects[PLAYER].pos();
+    let mut keys: Vec<(i32, i32)> = vec![];
+    for &(x, y) in &doors {
+        if keys.len() == MAX_LOCKED_DOORS {
+            break;
+        }
+        if rng.gen_range(0, 100) >= LOCKED_DOOR_CHANCE {
+            continue;
+        }
+        set_locked(map, (x, y), true);
+        let reached = flood_fill(map, start, Tile::is_passable);
+        if keys.iter().any(|key| !reached.contains(key)) {
+            set_locked(map, (x, y), false);
+            continue;
+        }
+        let free: Vec<(i32, i32)> = reached
+            .into_iter()
+            .filter(|&(x, y)| {
+                let tile = &map[x as usize][y as usize];
+                (x, y) != start
+                    && tile.door.is_none()
+                    && !tile.stairs
+                    && tile.portal.is_none()
+                    && !objects.iter().any(|object| object.pos() == (x, y))
+            })
+            .collect();
+        if free.is_empty() {
+            set_locked(map, (x, y), false);
+            continue;
+        }
+        let (key_x, key_y) = free[rng.gen_range(0, free.len())];
+        objects.push(key.spawn(key_x, key_y));
+        keys.push((key_x, key_y));
+    }
+}
+
+/// Locks or unlocks the door on the tile
+///
+fn set_locked(map: &mut Map, (x, y): (i32, i32), locked: bool) {
+    if let Some(door) = map[x as usize][y as usize].door.as_mut() {
+        door.locked = locked;
+    }
+}
+
+/// Opens the closed door on the tile if it isn't locked, as monsters do. Returns false if there
+/// is no such door
+///
+pub fn open_door(map: &mut Map, (x, y): (i32, i32)) -> bool {
+    let tile = &mut map[x as usize][y as usize];
+    match tile.door {
+        Some(door) if !door.open && !door.locked => {
+            tile.door = Some(Door {
+                open: true,
+                locked: false,
+            });
+            tile.blocked = false;
+            tile.block_sight = false;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The player walks into a closed door: an unlocked door opens, and a locked one is unlocked and
+/// opened with a key from the inventory. Returns false if there is no closed door on the tile
+///
+pub fn player_open_door(game: &mut Game, (x, y): (i32, i32)) -> bool {
+    let door = match game.map[x as usize][y as usize].door {
+        Some(door) if !door.open => door,
+        _ => return false,
+    };
+    if door.locked {
+        if !use_key(game) {
+            game.messages.add("The door is locked", WHITE);
+            return true;
+        }
+        set_locked(&mut game.map, (x, y), false);
+        game.messages.add("You unlock the door with a key", YELLOW);
+    } else {
+        game.messages.add("You open the door", WHITE);
+    }
+    open_door(&mut game.map, (x, y));
+    true
+}
+
+/// Uses up a key from the player's inventory. Returns false if they have none
+///
+fn use_key(game: &mut Game) -> bool {
+    let index = match game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Key))
+    {
+        Some(index) => index,
+        None => return false,
+    };
+    let was_burdened = is_burdened(&game.inventory);
+    if game.inventory[index].count > 1 {
+        game.inventory[index].count -= 1;
+    } else {
+        game.inventory.remove(index);
+    }
+    report_burden(was_burdened, game);
+    true
+}
+
+/// The player closes the open door on the tile, as long as nothing is standing or lying in the
+/// doorway. Returns true if it was closed
+///
+pub fn close_door(game: &mut Game, objects: &[Object], (x, y): (i32, i32)) -> bool {
+    let tile = &mut game.map[x as usize][y as usize];
+    match tile.door {
+        Some(door) if door.open => {
+            if objects.iter().any(|object| object.pos() == (x, y)) {
+                game.messages.add("Something is in the doorway", WHITE);
+                return false;
+            }
+            tile.door = Some(Door {
+                open: false,
+                locked: false,
+            });
+            tile.blocked = true;
+            tile.block_sight = true;
+            game.messages.add("You close the door", WHITE);
+            true
+        }
+        _ => {
+            game.messages.add("There is no open door there", WHITE);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // How many seeds each map style is built with
+    const SEEDS: u64 = 100;
+
+    #[test]
+    fn every_key_can_be_reached_without_going_through_a_locked_door() {
+        let raws = load_raws().unwrap();
+        let key = raws.items.iter().find(|item| item.item == Item::Key);
+        let mut locked_doors = 0;
+        for &style in MapStyle::ALL.iter() {
+            for seed in 0..SEEDS {
+                let mut rng = GameRng::new(seed);
+                let BuiltMap {
+                    mut map,
+                    rooms,
+                    start,
+                    ..
+                } = style.builder().build(&mut rng);
+                let mut objects = vec![Object::new(start.0, start.1, '@', "player", WHITE, true)];
+                place_doors(&mut map, &rooms, &mut objects, key, &mut rng);
+
+                let reached = flood_fill(&map, start, Tile::is_passable);
+                for object in objects
+                    .iter()
+                    .filter(|object| object.item == Some(Item::Key))
+                {
+                    assert!(
+                        reached.contains(&object.pos()),
+                        "{:?} with seed {}: the key at {:?} is behind a locked door",
+                        style,
+                        seed,
+                        object.pos()
+                    );
+                }
+                locked_doors += map
+                    .iter()
+                    .flatten()
+                    .filter(|tile| matches!(tile.door, Some(door) if door.locked))
+                    .count();
+            }
+        }
+        assert!(locked_doors > 0);
+    }
+}
@@ -0,0 +1,200 @@
+use super::*;
+
+// The kinds of timed status effect. Poison and burning hurt every turn, regeneration heals every
+// turn and confusion makes the fighter stumble about in random directions
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    Poison,
+    Burning,
+    Regeneration,
+    Confusion,
+}
+
+// How a new dose of an effect combines with the same effect a fighter already has. Refresh keeps
+// the longer of the two durations, Extend adds the new turns on top
+enum Stacking {
+    Refresh,
+    Extend,
+}
+
+// A status effect on a fighter, lasting for a number of turns. Strength is the damage or healing
+// done each turn, and means nothing for confusion. from_player is set by the game on effects the
+// player gave, so that they get the experience when it kills a monster
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusEffect {
+    pub kind: Effect,
+    #[serde(deserialize_with = "positive")]
+    pub turns: i32,
+    #[serde(deserialize_with = "positive")]
+    pub strength: i32,
+    #[serde(default)]
+    pub from_player: bool,
+}
+
+impl Effect {
+    fn stacking(self) -> Stacking {
+        match self {
+            Effect::Poison => Stacking::Extend,
+            Effect::Burning | Effect::Regeneration | Effect::Confusion => Stacking::Refresh,
+        }
+    }
+
+    /// Returns the color the effect is shown in
+    pub fn color(self) -> Color {
+        match self {
+            Effect::Poison => LIGHT_GREEN,
+            Effect::Burning => ORANGE,
+            Effect::Regeneration => LIGHT_VIOLET,
+            Effect::Confusion => LIGHT_CYAN,
+        }
+    }
+}
+
+impl std::fmt::Display for Effect {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Effect::Poison => write!(f, "poisoned"),
+            Effect::Burning => write!(f, "burning"),
+            Effect::Regeneration => write!(f, "regenerating"),
+            Effect::Confusion => write!(f, "confused"),
+        }
+    }
+}
+
+/// Returns true if the object currently suffers from or enjoys the effect
+///
+pub fn has_effect(object: &Object, kind: Effect) -> bool {
+    object.effects.iter().any(|effect| effect.kind == kind)
+}
+
+/// Gives a living fighter a status effect. If it already has the effect, the stronger strength is
+/// kept and the turns are combined by the effect's stacking rule
+///
+pub fn apply_effect(object: &mut Object, effect: StatusEffect, game: &mut Game) {
+    if object.fighter.is_none() || !object.alive {
+        return;
+    }
+    let current = object
+        .effects
+        .iter_mut()
+        .find(|current| current.kind == effect.kind);
+    match current {
+        Some(current) => {
+            current.strength = cmp::max(current.strength, effect.strength);
+            current.from_player = current.from_player || effect.from_player;
+            current.turns = match effect.kind.stacking() {
+                Stacking::Refresh => cmp::max(current.turns, effect.turns),
+                Stacking::Extend => current.turns + effect.turns,
+            };
+        }
+        None => {
+            object.effects.push(effect);
+            on_apply(object, effect, game);
+        }
+    }
+}
+
+/// Ticks the status effects of every living fighter by one turn: each effect acts, counts down and
+/// wears off once its turns run out. The player gets the experience for monsters killed by the
+/// effects they gave
+///
+pub fn tick_effects(game: &mut Game, objects: &mut [Object]) {
+    for id in 0..objects.len() {
+        if !objects[id].alive || objects[id].fighter.is_none() {
+            continue;
+        }
+        let mut remaining = vec![];
+        for mut effect in std::mem::take(&mut objects[id].effects) {
+            on_turn(id, effect, game, objects);
+            if !objects[id].alive {
+                // the dead suffer no more
+                break;
+            }
+            effect.turns -= 1;
+            if effect.turns > 0 {
+                remaining.push(effect);
+            } else {
+                on_expire(&objects[id], effect, game);
+            }
+        }
+        if objects[id].alive {
+            objects[id].effects = remaining;
+        }
+    }
+}
+
+/// Returns how messages refer to the object
+///
+fn subject(object: &Object) -> String {
+    if object.is_player() {
+        "you".into()
+    } else {
+        format!("the {}", object.name)
+    }
+}
+
+/// Returns true if the player can see what happens to the object
+///
+fn is_seen(object: &Object, game: &Game) -> bool {
+    object.is_player() || game.fov.is_in_fov(object.x, object.y)
+}
+
+/// Called when a fighter first gets an effect
+///
+fn on_apply(object: &Object, effect: StatusEffect, game: &mut Game) {
+    if !is_seen(object, game) {
+        return;
+    }
+    let message = if object.is_player() {
+        format!("You are {}!", effect.kind)
+    } else {
+        format!("The {} is {}!", object.name, effect.kind)
+    };
+    game.messages.add(message, effect.kind.color());
+}
+
+/// Called once per turn for every effect a fighter has. Confusion acts on the fighter's movement
+/// instead, see player_move_or_attack and ai_take_turn
+///
+fn on_turn(id: usize, effect: StatusEffect, game: &mut Game, objects: &mut [Object]) {
+    let (hurt, cause) = match effect.kind {
+        Effect::Poison => ("The poison hurts", "poison"),
+        Effect::Burning => ("The flames burn", "fire"),
+        Effect::Regeneration => {
+            objects[id].heal(effect.strength, game);
+            return;
+        }
+        Effect::Confusion => return,
+    };
+    if is_seen(&objects[id], game) {
+        game.messages.add(
+            format!(
+                "{} {} for {} HP",
+                hurt,
+                subject(&objects[id]),
+                effect.strength
+            ),
+            effect.kind.color(),
+        );
+    }
+    if let Some(xp) = objects[id].take_damage(effect.strength, cause, game) {
+        if id != PLAYER && effect.from_player {
+            objects[PLAYER].gain_xp(xp);
+        }
+    }
+}
+
+/// Called when an effect wears off
+///
+fn on_expire(object: &Object, effect: StatusEffect, game: &mut Game) {
+    if !is_seen(object, game) {
+        return;
+    }
+    let message = if object.is_player() {
+        format!("You are no longer {}", effect.kind)
+    } else {
+        format!("The {} is no longer {}", object.name, effect.kind)
+    };
+    game.messages.add(message, effect.kind.color());
+}
@@ -0,0 +1,178 @@
+use super::*;
+
+// How many turns a tile burns when set alight by a spell, or by the fuel on it
+const SPELL_FIRE_TURNS: i32 = 10;
+const FUEL_FIRE_TURNS: i32 = 6;
+
+// How many turns the smoke from a burnt-out tile hangs in the air, blocking sight
+const SMOKE_TURNS: i32 = 4;
+
+// How many turns a fighter keeps burning after stepping out of the flames
+const BURNING_TURNS: i32 = 3;
+
+// The chance out of 100 that fire spreads to fuel next to a burning tile each turn
+const SPREAD_CHANCE: u32 = 50;
+
+/// Sets every floor tile within range of the center on fire, as long as the center can be seen
+/// from it. Walls and smoke stop the flames. The player gets the experience for monsters the fire
+/// kills if they lit it
+///
+pub fn set_tiles_on_fire(game: &mut Game, center: (i32, i32), range: i32, by_player: bool) {
+    for x in (center.0 - range)..=(center.0 + range) {
+        for y in (center.1 - range)..=(center.1 + range) {
+            let (dx, dy) = (x - center.0, y - center.1);
+            if !in_map(x, y)
+                || dx.pow(2) + dy.pow(2) > range.pow(2)
+                || game.map[x as usize][y as usize].blocked
+                || !line_of_sight(&game.map, center, (x, y))
+            {
+                continue;
+            }
+            let tile = &mut game.map[x as usize][y as usize];
+            tile.fire = cmp::max(tile.fire, SPELL_FIRE_TURNS);
+            tile.lit_by_player = tile.lit_by_player || by_player;
+        }
+    }
+}
+
+/// Returns true if the object is an item that catches fire, such as a scroll
+///
+fn is_flammable_item(object: &Object) -> bool {
+    matches!(object.item, Some(item) if item.is_flammable())
+}
+
+/// Returns true if the object is the uneaten corpse of a dead monster
+///
+fn is_corpse(object: &Object) -> bool {
+    !object.alive && object.corpse.is_some()
+}
+
+/// Returns true if something on the tile can catch fire: grass, a corpse or a flammable item
+///
+fn has_fuel(map: &Map, objects: &[Object], (x, y): (i32, i32)) -> bool {
+    map[x as usize][y as usize].grass
+        || objects.iter().any(|object| {
+            object.pos() == (x, y) && (is_flammable_item(object) || is_corpse(object))
+        })
+}
+
+/// Steps the fire by one turn. Burning tiles set whoever stands on them burning, spread to fuel
+/// next to them, and burn up the grass, corpses and scrolls on them. Once a tile burns out it
+/// smokes for a few turns
+///
+pub fn step_fire(game: &mut Game, objects: &mut Vec<Object>) {
+    let burning: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| game.map[x as usize][y as usize].fire > 0)
+        .collect();
+
+    // The flames set the player and monsters alike burning, hotter fires burn harder
+    for object in objects.iter_mut() {
+        let tile = game.map[object.x as usize][object.y as usize];
+        if tile.fire == 0 {
+            continue;
+        }
+        let burning = StatusEffect {
+            kind: Effect::Burning,
+            turns: BURNING_TURNS,
+            strength: if tile.fire > 5 { 2 } else { 1 },
+            from_player: tile.lit_by_player,
+        };
+        apply_effect(object, burning, game);
+    }
+
+    // Fire spreads to fuel on the tiles around it, still the player's fire if they lit it
+    let mut catching: Vec<((i32, i32), bool)> = vec![];
+    for &(x, y) in &burning {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let next = (x + dx, y + dy);
+                if !in_map(next.0, next.1)
+                    || game.map[next.0 as usize][next.1 as usize].fire > 0
+                    || catching.iter().any(|&(tile, _)| tile == next)
+                    || !has_fuel(&game.map, objects, next)
+                {
+                    continue;
+                }
+                if game.rng.gen_range(0, 100) < SPREAD_CHANCE {
+                    let lit_by_player = game.map[x as usize][y as usize].lit_by_player;
+                    catching.push((next, lit_by_player));
+                }
+            }
+        }
+    }
+
+    // The fuel on burning tiles burns up, leaving the grass burnt away
+    for &(x, y) in &burning {
+        if !has_fuel(&game.map, objects, (x, y)) {
+            continue;
+        }
+        let burns = |object: &Object| {
+            object.pos() == (x, y) && (is_flammable_item(object) || is_corpse(object))
+        };
+        for object in objects.iter().filter(|object| burns(object)) {
+            if game.fov.is_in_fov(x, y) {
+                game.messages
+                    .add(format!("The {} burns up!", object.name), ORANGE);
+            }
+        }
+        objects.retain(|object| !burns(object));
+        let tile = &mut game.map[x as usize][y as usize];
+        tile.fire = cmp::max(tile.fire, FUEL_FIRE_TURNS);
+        tile.grass = false;
+    }
+    for ((x, y), lit_by_player) in catching {
+        let tile = &mut game.map[x as usize][y as usize];
+        tile.fire = FUEL_FIRE_TURNS;
+        tile.lit_by_player = lit_by_player;
+    }
+
+    // Fires and smoke die down
+    for tile in game.map.iter_mut().flatten() {
+        if tile.fire > 0 {
+            tile.fire -= 1;
+            if tile.fire == 0 {
+                tile.smoke = SMOKE_TURNS;
+                tile.lit_by_player = false;
+            }
+        } else if tile.smoke > 0 {
+            tile.smoke -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets a drudge with 1 HP left on fire and steps the fire and status effects until it dies.
+    /// Returns the experience the player got for it
+    fn burn_drudge(by_player: bool) -> i32 {
+        let (mut game, mut objects) = new_game(load_raws().unwrap(), 1, None);
+        game.map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        objects.truncate(1);
+        objects[PLAYER].set_pos(5, 5);
+        let drudge = game.raws.monsters.iter().find(|raw| raw.name == "drudge");
+        let mut drudge = drudge.unwrap().spawn(20, 20);
+        drudge.fighter.as_mut().unwrap().hp = 1;
+        objects.push(drudge);
+
+        set_tiles_on_fire(&mut game, (20, 20), 1, by_player);
+        step_fire(&mut game, &mut objects);
+        tick_effects(&mut game, &mut objects);
+        assert!(!objects[1].alive);
+        objects[PLAYER].fighter.unwrap().xp
+    }
+
+    #[test]
+    fn player_gets_experience_for_monsters_their_fire_kills() {
+        let raws = load_raws().unwrap();
+        let drudge = raws
+            .monsters
+            .iter()
+            .find(|raw| raw.name == "drudge")
+            .unwrap();
+        assert_eq!(burn_drudge(true), drudge.xp);
+        assert_eq!(burn_drudge(false), 0);
+    }
+}
@@ -0,0 +1,312 @@
+use super::*;
+use std::io::BufRead;
+
+// A frontend without a window. Commands are read one per line from input and every new game
+// message is written to output, so the game can be scripted and run on a machine with no display
+//
+//   up, down, left, right   move or attack
+//   pickup                  pick up an item or eat a corpse
+//   descend                 go down the stairs
+//   use <letter>            use an inventory item
+//   drop <letter>           drop an inventory item on the floor
+//   throw <letter>          throw an inventory item at a target
+//   close <direction>       close the door next to the player, up, down, left or right
+//   search                  search the tiles around the player for traps
+//   disarm <direction>      disarm a trap the player knows about next to them
+//   history [text]          show the message history, or only the messages containing text
+//   quit                    end the game
+//
+// Menus are answered with the letter of an option and targets with "<x> <y>"
+pub struct HeadlessFrontend<R, W> {
+    input: R,
+    output: W,
+    messages_shown: usize,
+    closed: bool,
+}
+
+impl<R: BufRead, W: Write> HeadlessFrontend<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        HeadlessFrontend {
+            input,
+            output,
+            messages_shown: 0,
+            closed: false,
+        }
+    }
+
+    /// Returns the next line of input with surrounding whitespace removed, or None once the input
+    /// has ended
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                self.closed = true;
+                None
+            }
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+
+    /// Writes a line to output. The game carries on if output can no longer be written
+    fn write_line(&mut self, text: &str) {
+        let _ = writeln!(self.output, "{}", text);
+    }
+}
+
+impl<R: BufRead, W: Write> Renderer for HeadlessFrontend<R, W> {
+    fn render(&mut self, game: &Game, _objects: &[Object]) {
+        let total = game.messages.total();
+        for message in game
+            .messages
+            .latest(total.saturating_sub(self.messages_shown))
+        {
+            self.write_line(message);
+        }
+        self.messages_shown = total;
+        let _ = self.output.flush();
+    }
+}
+
+impl<R: BufRead, W: Write> Input for HeadlessFrontend<R, W> {
+    fn next_command(&mut self, game: &Game, _objects: &[Object]) -> Option<Command> {
+        let line = match self.read_line() {
+            Some(line) => line,
+            None => return Some(Command::Exit),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => None,
+            ["up"] => Some(Command::Move(0, -1)),
+            ["down"] => Some(Command::Move(0, 1)),
+            ["left"] => Some(Command::Move(-1, 0)),
+            ["right"] => Some(Command::Move(1, 0)),
+            ["pickup"] => Some(Command::PickUp),
+            ["descend"] => Some(Command::Descend),
+            ["use", letter] => match letter_index(letter) {
+                Some(index) if index < game.inventory.len() => Some(Command::UseItem(index)),
+                _ => {
+                    self.write_line(&format!("There is no item ({}) to use", letter));
+                    None
+                }
+            },
+            ["history", ..] => {
+                let search = line["history".len()..].trim().to_lowercase();
+                let history: Vec<String> = game
+                    .messages
+                    .iter()
+                    .map(|message| message.to_string())
+                    .filter(|message| message.to_lowercase().contains(&search))
+                    .collect();
+                for message in history {
+                    self.write_line(&message);
+                }
+                None
+            }
+            ["drop", letter] => match letter_index(letter) {
+                Some(index) if index < game.inventory.len() => Some(Command::DropItem(index)),
+                _ => {
+                    self.write_line(&format!("There is no item ({}) to drop", letter));
+                    None
+                }
+            },
+            ["throw", letter] => match letter_index(letter) {
+                Some(index) if index < game.inventory.len() => Some(Command::ThrowItem(index)),
+                _ => {
+                    self.write_line(&format!("There is no item ({}) to throw", letter));
+                    None
+                }
+            },
+            ["close", direction] => match direction_step(direction) {
+                Some((dx, dy)) => Some(Command::CloseDoor(dx, dy)),
+                None => {
+                    self.write_line(&format!("There is no direction \"{}\"", direction));
+                    None
+                }
+            },
+            ["search"] => Some(Command::Search),
+            ["disarm", direction] => match direction_step(direction) {
+                Some((dx, dy)) => Some(Command::DisarmTrap(dx, dy)),
+                None => {
+                    self.write_line(&format!("There is no direction \"{}\"", direction));
+                    None
+                }
+            },
+            ["quit"] => Some(Command::Exit),
+            _ => {
+                self.write_line(&format!("Unknown command \"{}\"", line));
+                None
+            }
+        }
+    }
+
+    fn menu(&mut self, header: &str, options: &[String], _width: i32) -> Option<usize> {
+        self.write_line(header.trim_end());
+        for (index, option) in options.iter().enumerate() {
+            let text = format!("({}) {}", (b'a' + index as u8) as char, option);
+            self.write_line(&text);
+        }
+        let _ = self.output.flush();
+        let line = self.read_line()?;
+        letter_index(&line).filter(|&index| index < options.len())
+    }
+
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        _start: (i32, i32),
+        max_range: f32,
+    ) -> Option<(i32, i32)> {
+        // the instructions for the tcod cursor are shown first
+        self.render(game, objects);
+        let line = self.read_line()?;
+        let mut coords = line.split_whitespace().map(|word| word.parse::<i32>());
+        match (coords.next(), coords.next(), coords.next()) {
+            (Some(Ok(x)), Some(Ok(y)), None)
+                if game.fov.is_in_fov(x, y) && objects[PLAYER].distance(x, y) <= max_range =>
+            {
+                Some((x, y))
+            }
+            _ => None,
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Converts a direction to the step that goes that way
+///
+fn direction_step(direction: &str) -> Option<(i32, i32)> {
+    match direction {
+        "up" => Some((0, -1)),
+        "down" => Some((0, 1)),
+        "left" => Some((-1, 0)),
+        "right" => Some((1, 0)),
+        _ => None,
+    }
+}
+
+/// Converts a menu letter to the index of the option it stands for
+///
+fn letter_index(text: &str) -> Option<usize> {
+    match text.as_bytes() {
+        [letter @ b'a'..=b'z'] => Some((letter - b'a') as usize),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portals::place_portal_pair;
+    use std::io::{self, Cursor};
+
+    const SEED: u64 = 7;
+
+    /// Returns a new game on an open map without walls, with the player alone at (20, 20)
+    fn open_game() -> (Game, Vec<Object>) {
+        let (mut game, mut objects) = new_game(load_raws().unwrap(), SEED, None);
+        game.map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        objects.truncate(1);
+        objects[PLAYER].set_pos(20, 20);
+        (game, objects)
+    }
+
+    /// Adds a monster of the named type at the position and returns its id
+    fn add_monster(
+        name: &str,
+        (x, y): (i32, i32),
+        game: &Game,
+        objects: &mut Vec<Object>,
+    ) -> usize {
+        let raw = game
+            .raws
+            .monsters
+            .iter()
+            .find(|raw| raw.name == name)
+            .unwrap();
+        objects.push(raw.spawn(x, y));
+        objects.len() - 1
+    }
+
+    /// Plays the script, one command per line, until it runs out
+    fn play(script: &str, game: &mut Game, objects: &mut Vec<Object>) {
+        let mut frontend = HeadlessFrontend::new(Cursor::new(script), io::sink());
+        play_game(&mut frontend, game, objects);
+    }
+
+    #[test]
+    fn melee_kill_gives_xp() {
+        let (mut game, mut objects) = open_game();
+        let drudge = add_monster("drudge", (21, 20), &game, &mut objects);
+        // the drudge stands still, so every step right attacks it until it dies
+        objects[drudge].ai = None;
+        let xp = objects[drudge].fighter.unwrap().xp;
+        play(&"right\n".repeat(20), &mut game, &mut objects);
+        assert!(!objects[drudge].alive);
+        assert_eq!(objects[PLAYER].fighter.unwrap().xp, xp);
+        assert_eq!(game.stats.kills, 1);
+    }
+
+    #[test]
+    fn portal_moves_the_player() {
+        let (mut game, mut objects) = open_game();
+        place_portal_pair(&mut game.map, (21, 20), (50, 30), 10, false);
+        play("right\n", &mut game, &mut objects);
+        assert!(objects[PLAYER].distance(50, 30) < 2.0);
+        assert!(game.map[50][30]
+            .portal
+            .is_some_and(|portal| !portal.is_open()));
+    }
+
+    #[test]
+    fn archer_shoots_only_along_a_clear_line() {
+        let (mut game, mut objects) = open_game();
+        let archer = add_monster("goblin archer", (25, 20), &game, &mut objects);
+        play("search\n", &mut game, &mut objects);
+        let shots = |game: &Game| {
+            game.messages
+                .iter()
+                .filter(|message| message.to_string() == "The goblin archer shoots at you")
+                .count()
+        };
+        assert_eq!(shots(&game), 1);
+        assert_eq!(objects[archer].pos(), (25, 20));
+
+        // a drudge in the line of fire makes the archer move instead
+        let drudge = add_monster("drudge", (23, 20), &game, &mut objects);
+        objects[drudge].ai = None;
+        play("search\n", &mut game, &mut objects);
+        assert_eq!(shots(&game), 1);
+        assert_ne!(objects[archer].pos(), (25, 20));
+    }
+
+    #[test]
+    fn fire_ring_burns_a_monster_then_burns_out() {
+        let (mut game, mut objects) = open_game();
+        let rabbit = add_monster("white rabbit", (27, 20), &game, &mut objects);
+        let scroll = game
+            .raws
+            .items
+            .iter()
+            .find(|raw| raw.name == "Fire Ring Scroll");
+        game.inventory.push(scroll.unwrap().spawn(0, 0));
+        let hp = objects[rabbit].fighter.unwrap().hp;
+
+        play("use a\n27 20\nsearch\n", &mut game, &mut objects);
+        assert!(game.inventory.is_empty());
+        assert!(game.map[27][20].fire > 0);
+        assert!(has_effect(&objects[rabbit], Effect::Burning));
+        assert!(objects[rabbit].fighter.unwrap().hp < hp - 20);
+
+        // the rabbit stays in the flames until they burn out
+        objects[rabbit].ai = None;
+
+        play(&"search\n".repeat(30), &mut game, &mut objects);
+        assert!(game.map.iter().flatten().all(|tile| tile.fire == 0));
+        assert!(!has_effect(&objects[rabbit], Effect::Burning));
+        assert!(objects[PLAYER].alive);
+    }
+}
@@ -0,0 +1,224 @@
+use super::*;
+
+// The player starts a game with START_FOOD, burns one food every turn, and can hold at most
+// MAX_FOOD. The other constants are where the hunger states begin
+pub const START_FOOD: i32 = 1000;
+const MAX_FOOD: i32 = 2000;
+const SATIATED_FOOD: i32 = 1500;
+const HUNGRY_FOOD: i32 = 300;
+const WEAK_FOOD: i32 = 100;
+
+// A fainting player loses a turn to a faint with this chance out of 100 every turn, and starves
+// for STARVATION_DAMAGE every turn
+const FAINT_CHANCE: u32 = 10;
+const STARVATION_DAMAGE: i32 = 1;
+
+// How hungry the player is. A satiated player is too full to eat, a weak one hits for less, and a
+// fainting one starves and sometimes faints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hunger {
+    Satiated,
+    Normal,
+    Hungry,
+    Weak,
+    Fainting,
+}
+
+// What is left to eat of a monster, set per monster type in RAWS_FILE. Eating it gives the
+// player food, and it can be eaten until it rots rot_turns after the monster died
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Corpse {
+    #[serde(deserialize_with = "positive")]
+    pub food: i32,
+    #[serde(deserialize_with = "positive")]
+    pub rot_turns: i32,
+    #[serde(default)]
+    pub side_effect: Option<SideEffect>,
+}
+
+// Something that happens to whoever eats a corpse, with the chance out of 100
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SideEffect {
+    #[serde(deserialize_with = "positive")]
+    pub chance: u32,
+    pub effect: CorpseEffect,
+}
+
+// The side effects of eating a corpse: a status effect such as poison, or a permanent boost to one
+// of the player's stats
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CorpseEffect {
+    Status(StatusEffect),
+    MaxHp(i32),
+    Power(i32),
+    Defense(i32),
+}
+
+impl Hunger {
+    /// Returns the hunger state for an amount of food
+    pub fn from_food(food: i32) -> Self {
+        if food > SATIATED_FOOD {
+            Hunger::Satiated
+        } else if food > HUNGRY_FOOD {
+            Hunger::Normal
+        } else if food > WEAK_FOOD {
+            Hunger::Hungry
+        } else if food > 0 {
+            Hunger::Weak
+        } else {
+            Hunger::Fainting
+        }
+    }
+
+    /// Returns the color the state is shown in
+    pub fn color(self) -> Color {
+        match self {
+            Hunger::Satiated => LIGHT_GREEN,
+            Hunger::Normal => WHITE,
+            Hunger::Hungry => YELLOW,
+            Hunger::Weak => ORANGE,
+            Hunger::Fainting => RED,
+        }
+    }
+
+    /// Returns how much the state takes off the player's attack power
+    pub fn power_penalty(self) -> i32 {
+        match self {
+            Hunger::Weak | Hunger::Fainting => 1,
+            Hunger::Satiated | Hunger::Normal | Hunger::Hungry => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Hunger {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Hunger::Satiated => write!(f, "Satiated"),
+            Hunger::Normal => write!(f, "Normal"),
+            Hunger::Hungry => write!(f, "Hungry"),
+            Hunger::Weak => write!(f, "Weak"),
+            Hunger::Fainting => write!(f, "Fainting"),
+        }
+    }
+}
+
+/// Burns one turn's worth of the player's food. The player is told when they get hungrier, and
+/// starves once they are fainting
+///
+pub fn tick_hunger(game: &mut Game, objects: &mut [Object]) {
+    let before = game.hunger();
+    game.food = cmp::max(game.food - 1, -1);
+    let after = game.hunger();
+    if after != before {
+        let message = match after {
+            Hunger::Normal => "You are no longer satiated",
+            Hunger::Hungry => "You are getting hungry",
+            Hunger::Weak => "You are weak with hunger!",
+            Hunger::Fainting => "You are fainting from hunger!",
+            Hunger::Satiated => return,
+        };
+        game.messages.add(message, after.color());
+    }
+    if after == Hunger::Fainting {
+        objects[PLAYER].take_damage(STARVATION_DAMAGE, "starvation", game);
+    }
+}
+
+/// Returns true if a fainting player faints this turn, losing it to the monsters
+///
+pub fn faints(game: &mut Game) -> bool {
+    if game.hunger() == Hunger::Fainting && game.rng.gen_range(0, 100) < FAINT_CHANCE {
+        game.messages.add("You faint from hunger!", RED);
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the id of an uneaten corpse at the position, if there is one
+///
+pub fn corpse_at(objects: &[Object], pos: (i32, i32)) -> Option<usize> {
+    objects
+        .iter()
+        .position(|object| object.pos() == pos && !object.alive && object.corpse.is_some())
+}
+
+/// The player eats the corpse with the given id, gaining its food and maybe its side effect, and
+/// leaves the bones behind. A satiated player is too full to eat. Returns true if it was eaten
+///
+pub fn eat_corpse(id: usize, game: &mut Game, objects: &mut [Object]) -> bool {
+    let corpse = match objects[id].corpse {
+        Some(corpse) => corpse,
+        None => return false,
+    };
+    if game.hunger() == Hunger::Satiated {
+        game.messages.add("You are too full to eat", WHITE);
+        return false;
+    }
+    game.food = cmp::min(game.food + corpse.food, MAX_FOOD);
+    game.messages
+        .add(format!("You eat the {}", objects[id].name), LIGHT_VIOLET);
+    objects[id].corpse = None;
+    objects[id].char = '_';
+
+    if let Some(side_effect) = corpse.side_effect {
+        if game.rng.gen_range(0, 100) < side_effect.chance {
+            apply_side_effect(side_effect.effect, game, &mut objects[PLAYER]);
+        }
+    }
+    true
+}
+
+/// Gives the player the side effect of a corpse they ate
+///
+fn apply_side_effect(effect: CorpseEffect, game: &mut Game, player: &mut Object) {
+    if let CorpseEffect::Status(status) = effect {
+        apply_effect(player, status, game);
+        return;
+    }
+    let fighter = match player.fighter.as_mut() {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let message = match effect {
+        CorpseEffect::MaxHp(amount) => {
+            fighter.base_max_hp += amount;
+            fighter.hp += amount;
+            format!("You feel healthier! (+{} max HP)", amount)
+        }
+        CorpseEffect::Power(amount) => {
+            fighter.base_power += amount;
+            format!("You feel stronger! (+{} attack)", amount)
+        }
+        CorpseEffect::Defense(amount) => {
+            fighter.base_defense += amount;
+            format!("You feel tougher! (+{} defense)", amount)
+        }
+        CorpseEffect::Status(_) => return,
+    };
+    game.messages.add(message, LIGHT_VIOLET);
+}
+
+/// Counts down the turns until each uneaten corpse rots. A rotten corpse can't be eaten any more
+/// and only its bones are left
+///
+pub fn tick_corpses(game: &mut Game, objects: &mut [Object]) {
+    for object in objects.iter_mut().filter(|object| !object.alive) {
+        let corpse = match object.corpse.as_mut() {
+            Some(corpse) => corpse,
+            None => continue,
+        };
+        corpse.rot_turns -= 1;
+        if corpse.rot_turns > 0 {
+            continue;
+        }
+        object.corpse = None;
+        object.char = '_';
+        if game.fov.is_in_fov(object.x, object.y) {
+            game.messages
+                .add(format!("The {} rot away", object.name), DARK_GREEN);
+        }
+    }
+}
@@ -1,95 +1,176 @@
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
 use rand::Rng;
+use serde::de::{self, Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
 use std::cmp;
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
 use tcod::colors::*;
-use tcod::console::*;
-use tcod::map::{FovAlgorithm, Map as FovMap};
+
+mod combat;
+mod doors;
+mod effects;
+mod fire;
+mod headless;
+mod hunger;
+mod mapgen;
+mod pathfinding;
+mod portals;
+mod replay;
+mod tcod_frontend;
+mod throwing;
+mod traps;
+
+use combat::{expected_damage, roll_attack};
+use doors::{close_door, open_door, place_doors, player_open_door, Door};
+use effects::{apply_effect, has_effect, tick_effects, Effect, StatusEffect};
+use fire::{set_tiles_on_fire, step_fire};
+use headless::HeadlessFrontend;
+use hunger::{
+    corpse_at, eat_corpse, faints, tick_corpses, tick_hunger, Corpse, Hunger, START_FOOD,
+};
+use mapgen::{grow_grass, join_cut_off_floor, region_center, validate_level, BuiltMap, MapStyle};
+use pathfinding::find_path;
+use portals::{place_portals, tick_portals, use_portals, Portal};
+use replay::{Recorder, Replay, ReplayPlayer};
+use tcod_frontend::TcodFrontend;
+use throwing::{flight, throw_item};
+use traps::{disarm_trap, known_trap, place_traps, spot_traps, spring_trap, Trap};
 
 // Field of View
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 const FOV_LIGHT_WALLS: bool = true;
 const TORCH_RADIUS: i32 = 15;
 
-// The entire size of the window
-const SCREEN_WIDTH: i32 = 120;
-const SCREEN_HEIGHT: i32 = 80;
-
 // Dimensions of the map, and rooms
 const MAP_WIDTH: i32 = 80;
 const MAP_HEIGHT: i32 = 43;
 const INVENTORY_WIDTH: i32 = 50;
+const MAIN_MENU_WIDTH: i32 = 24;
 const ROOM_MAX_SIZE: i32 = 20;
 const ROOM_MIN_SIZE: i32 = 5;
 
-// Room numbers and contents
+// Room numbers
 const MAX_ROOMS: i32 = 30;
-const MAX_ROOM_MONSTERS: i32 = 2;
-const MAX_ROOM_ITEMS: i32 = 1;
-
-// Misc properties
-const CORPSE_CONSUME_HP: i32 = 2;
-const HEAL_AMOUNT: i32 = 10;
-const RING_RANGE: i32 = 4;
-const FIRE_RING_DAMAGE: i32 = 20;
-
-// Panel and messaging interface
-const BAR_WIDTH: i32 = 20;
-const PANEL_HEIGHT: i32 = 7;
-const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
-const MSG_X: i32 = BAR_WIDTH + 2;
-const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
-
-// FPS
-const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
+
+// How many times a level is generated before one that fails validation is kept anyway
+const MAX_MAP_ATTEMPTS: usize = 10;
+
+// The steps to the eight tiles around a tile
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 1),
+    (1, 0),
+    (0, 1),
+    (-1, 1),
+    (1, -1),
+    (-1, 0),
+    (0, -1),
+    (-1, -1),
+];
+
+// How many messages the history keeps before the oldest are dropped
+const MESSAGE_LIMIT: usize = 500;
+
+// Menu options are chosen by letter, so a menu has at most one option per letter. This also caps
+// the inventory, where each stack of items takes one letter
+const MAX_MENU_OPTIONS: usize = 26;
+
+// Carrying more than BURDENED_WEIGHT slows the player down, and nothing more can be picked up
+// past MAX_CARRY_WEIGHT
+const BURDENED_WEIGHT: i32 = 40;
+const MAX_CARRY_WEIGHT: i32 = 80;
+
+// Experience and level-ups
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+const LEVEL_SCREEN_WIDTH: i32 = 40;
 
 // Player is always 0 in Objects
 const PLAYER: usize = 0;
 
-// RGB data for various Tile states
-const COLOR_DARK_WALL: Color = Color {
-    r: 120,
-    g: 120,
-    b: 120,
-};
-const COLOR_LIGHT_WALL: Color = Color {
-    r: 255,
-    g: 205,
-    b: 105,
-};
-const COLOR_LIGHT_PERIMETER: Color = Color {
-    r: 100,
-    g: 100,
-    b: 100,
-};
-const COLOR_DARK_PERIMETER: Color = Color {
-    r: 40,
-    g: 40,
-    b: 40,
-};
-const COLOR_DARK_GROUND: Color = Color {
-    r: 65,
-    g: 90,
-    b: 50,
-};
-const COLOR_LIGHT_TELEPORT: Color = Color { r: 0, g: 0, b: 225 };
-const COLOR_DARK_TELEPORT: Color = Color { r: 0, g: 0, b: 130 };
+// Save game file and format version. Bump the version whenever a saved type changes shape
+const SAVE_FILE: &str = "savegame";
+const SAVE_VERSION: u32 = 22;
 
-// Main struct for passing game states root, con, panel and FOV
-struct Tcod {
-    root: Root,
-    con: Offscreen,
-    panel: Offscreen,
-    fov: FovMap,
-}
+// Monster and item definitions
+const RAWS_FILE: &str = "raws.json";
 
 // The map is a vector of Tile vectors and each Tile is accessed as in 'map[x][y]'
 type Map = Vec<Vec<Tile>>;
 
-// Game struct contains the map, messages, and inventory
+// Game struct contains the map, messages, inventory, how deep the player is in the dungeon, how
+// much food the player has left, how the run is going, and the seeded random number generator
+// every random decision is made with. Levels are generated in map_style, or a style picked at
+// random for each level if it is None.
+// The raws are loaded from RAWS_FILE at startup and the FOV is recomputed every turn, so neither
+// is saved
+#[derive(Serialize, Deserialize)]
 struct Game {
     map: Map,
     messages: Messages,
     inventory: Vec<Object>,
+    dungeon_level: u32,
+    map_style: Option<MapStyle>,
+    food: i32,
+    stats: Stats,
+    seed: u64,
+    rng: GameRng,
+    #[serde(skip)]
+    raws: Raws,
+    #[serde(skip)]
+    fov: Fov,
+}
+
+impl Game {
+    /// Returns how hungry the player is
+    pub fn hunger(&self) -> Hunger {
+        Hunger::from_food(self.food)
+    }
+}
+
+// How the current run has gone, shown on the game-over screen. Turns counts the player's turns
+// and kills every monster that died, whatever killed it
+#[derive(Default, Serialize, Deserialize)]
+struct Stats {
+    turns: u32,
+    kills: u32,
+    cause_of_death: Option<String>,
+}
+
+// The game's random number generator (xorshift64*). Its state is saved with the game, so the same
+// seed always produces the same maps, spawns, and portals
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    /// Creates a generator from a seed. The seed is scrambled with splitmix64 so that similar
+    /// seeds give unrelated dungeons, and a state of 0 (which xorshift never leaves) is avoided
+    pub fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        GameRng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
 }
 
 // Player action can for each game tick can be one of three actions
@@ -100,123 +181,425 @@ enum PlayerAction {
     Exit,
 }
 
-// A Tile is a single square on the Map which contains a number of properties
-#[derive(Clone, Copy, Debug)]
+// A command from the player, given by whichever frontend is driving the game
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Command {
+    Move(i32, i32),
+    PickUp,
+    Descend,
+    UseItem(usize),
+    DropItem(usize),
+    ThrowItem(usize),
+    CloseDoor(i32, i32),
+    Search,
+    DisarmTrap(i32, i32),
+    Exit,
+}
+
+// Draws the game state. The game logic never draws anything itself
+trait Renderer {
+    /// Shows the map, the objects in the player's FOV, and the player's stats and messages
+    fn render(&mut self, game: &Game, objects: &[Object]);
+
+    /// Clears the screen, so that a menu can be shown with no game behind it
+    fn clear(&mut self) {}
+}
+
+// Asks the player for commands and choices. The game logic never reads input itself
+trait Input {
+    /// Returns the player's next command, or None if nothing happened that the game needs to act on
+    fn next_command(&mut self, game: &Game, objects: &[Object]) -> Option<Command>;
+
+    /// Asks the player to pick one of the options and returns its index, or None if cancelled
+    fn menu(&mut self, header: &str, options: &[String], width: i32) -> Option<usize>;
+
+    /// Asks the player for a tile in their FOV within max_range of the player, or None if cancelled
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        start: (i32, i32),
+        max_range: f32,
+    ) -> Option<(i32, i32)>;
+
+    /// Returns true once no more input will come, for example because the window was closed
+    fn closed(&self) -> bool;
+}
+
+// A frontend both draws the game and takes the player's input. The tcod window and the headless
+// frontend drive the same game logic
+trait Frontend: Renderer + Input {}
+
+impl<T: Renderer + Input> Frontend for T {}
+
+// A Tile is a single square on the Map which contains a number of properties. fire and smoke
+// count down the turns the tile has left burning or smoking. A tile with a portal is one pad of
+// a linked pair. A tile with a door is blocked while the door is closed. A trap stays hidden
+// until the player finds it. Grass is fuel, so fire spreads across it until it has burnt away.
+// lit_by_player marks fires the player started, which spread to the fuel around them
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 struct Tile {
     blocked: bool,
     block_sight: bool,
     perimeter: bool,
-    teleport: bool,
+    portal: Option<Portal>,
+    door: Option<Door>,
+    trap: Option<Trap>,
+    grass: bool,
+    stairs: bool,
     explored: bool,
-    has_corpse: bool,
-    on_fire: (bool, i32),
+    fire: i32,
+    lit_by_player: bool,
+    smoke: i32,
 }
 
-// Items can be used or their use can be cancelled if there was an error is usage
+// Items can be used, used and kept (equipment), or their use can be cancelled if there was an
+// error is usage
 enum UseResult {
     UsedUp,
+    UsedAndKept,
     Cancelled,
 }
 
-// Items which are non-fighting objects
-#[derive(Clone, Copy, Debug, PartialEq)]
+// Items which are non-fighting objects. Each kind carries the numbers it is balanced with, which
+// are set in RAWS_FILE
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 enum Item {
-    Heal,
-    FireRing,
+    Heal {
+        #[serde(deserialize_with = "positive")]
+        amount: i32,
+    },
+    FireRing {
+        #[serde(deserialize_with = "positive")]
+        damage: i32,
+        #[serde(deserialize_with = "positive")]
+        radius: i32,
+        #[serde(deserialize_with = "positive")]
+        range: f32,
+    },
+    Fireball {
+        #[serde(deserialize_with = "positive")]
+        damage: i32,
+        #[serde(deserialize_with = "positive")]
+        radius: i32,
+        #[serde(deserialize_with = "positive")]
+        range: f32,
+    },
+    Lightning {
+        #[serde(deserialize_with = "positive")]
+        damage: i32,
+        #[serde(deserialize_with = "positive")]
+        range: f32,
+    },
+    Potion(StatusEffect),
+    Equipment,
+    Key,
+    Missile {
+        #[serde(deserialize_with = "positive")]
+        damage: i32,
+    },
+}
+
+// An item that can be equipped in a slot and gives bonuses to the wearer's Fighter stats
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Equipment {
+    slot: Slot,
+    #[serde(default)]
+    equipped: bool,
+    max_hp_bonus: i32,
+    power_bonus: i32,
+    defense_bonus: i32,
+}
+
+// Each slot can only have a single item equipped
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Slot {
+    Weapon,
+    Armour,
+    Shield,
+}
+
+impl Item {
+    /// Returns true if the item burns up in a fire. Scrolls burn, potions and equipment do not
+    pub fn is_flammable(&self) -> bool {
+        matches!(
+            self,
+            Item::FireRing { .. } | Item::Fireball { .. } | Item::Lightning { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::Weapon => write!(f, "weapon"),
+            Slot::Armour => write!(f, "armour"),
+            Slot::Shield => write!(f, "shield"),
+        }
+    }
 }
 
-/// Increases a fighter object's HP by HEAL_AMOUNT
+/// Increases a fighter object's HP by the amount of the healing item
 ///
 fn cast_heal(
-    _inventory_id: usize,
-    _tcod: &mut Tcod,
+    inventory_id: usize,
+    _frontend: &mut dyn Frontend,
     game: &mut Game,
     objects: &mut [Object],
 ) -> UseResult {
+    let amount = match game.inventory[inventory_id].item {
+        Some(Item::Heal { amount }) => amount,
+        _ => return UseResult::Cancelled,
+    };
+
     // heal the player
     if let Some(fighter) = objects[PLAYER].fighter {
-        if fighter.hp == fighter.max_hp {
+        if fighter.hp == objects[PLAYER].max_hp(game) {
             game.messages.add("You are already at full health", RED);
             return UseResult::Cancelled;
         }
         game.messages.add(
-            format!("You have healed yourself for {} HP", HEAL_AMOUNT),
+            format!("You have healed yourself for {} HP", amount),
             LIGHT_VIOLET,
         );
-        objects[PLAYER].heal(HEAL_AMOUNT);
+        objects[PLAYER].heal(amount, game);
         return UseResult::UsedUp;
     }
     UseResult::Cancelled
 }
 
-/// Casts a ring of fire around the player which causes direct damage if a fighter object is in
-/// range or indirect if a fighter object comes into its range and the tile is still "hot"
+/// Gives the player the status effect of the potion
 ///
-fn cast_fire_ring(
+fn cast_potion(
+    inventory_id: usize,
+    _frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    match game.inventory[inventory_id].item {
+        Some(Item::Potion(effect)) => {
+            game.messages.add(
+                format!("You drink the {}", game.inventory[inventory_id].name),
+                LIGHT_VIOLET,
+            );
+            apply_effect(&mut objects[PLAYER], effect, game);
+            UseResult::UsedUp
+        }
+        _ => UseResult::Cancelled,
+    }
+}
+
+/// Keys are used by walking into a locked door, so the player is told how
+///
+fn cast_key(
     _inventory_id: usize,
-    tcod: &mut Tcod,
+    _frontend: &mut dyn Frontend,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    game.messages
+        .add("Walk into a locked door to unlock it with the key", WHITE);
+    UseResult::UsedAndKept
+}
+
+/// Missiles such as rocks and daggers are thrown rather than used, so the player is told so
+///
+fn cast_missile(
+    inventory_id: usize,
+    _frontend: &mut dyn Frontend,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    game.messages.add(
+        format!(
+            "Throw the {} at a monster to hurt it",
+            game.inventory[inventory_id].name
+        ),
+        WHITE,
+    );
+    UseResult::UsedAndKept
+}
+
+/// Casts a ring of fire around a targeted tile which causes direct damage if a fighter object is
+/// in range or indirect if a fighter object comes into its range and the tile is still "hot"
+///
+fn cast_fire_ring(
+    inventory_id: usize,
+    frontend: &mut dyn Frontend,
     game: &mut Game,
     objects: &mut [Object],
 ) -> UseResult {
-    // target all monsters within the RING_RANGE of the player
-    let monster_ids = get_monsters_in_range(tcod, objects, RING_RANGE);
+    let (damage, radius, range) = match game.inventory[inventory_id].item {
+        Some(Item::FireRing {
+            damage,
+            radius,
+            range,
+        }) => (damage, radius, range),
+        _ => return UseResult::Cancelled,
+    };
+
+    // ask the player for the center of the ring, starting on the player
+    game.messages.add(
+        "Choose the center of the fire ring with the arrows or mouse, Enter or left-click to cast, \
+         Esc or right-click to cancel",
+        LIGHT_CYAN,
+    );
+    let start = objects[PLAYER].pos();
+    let center = match frontend.target_tile(game, objects, start, range) {
+        Some(center) => center,
+        None => return UseResult::Cancelled,
+    };
+
+    // target all monsters within the radius of the center
+    let monster_ids = get_monsters_in_range(&game.fov, objects, center, radius);
     let mut no_effect: bool = true;
     for monster_id in monster_ids {
         if let Some(monster_id) = monster_id {
             game.messages.add(
                 format!(
                     "Fire ring conflagrated the {} for {} hit points",
-                    objects[monster_id].name, FIRE_RING_DAMAGE
+                    objects[monster_id].name, damage
                 ),
                 LIGHT_BLUE,
             );
             no_effect = false;
-            objects[monster_id].take_damage(FIRE_RING_DAMAGE, game);
+            if let Some(xp) = objects[monster_id].take_damage(damage, "a fire ring", game) {
+                objects[PLAYER].gain_xp(xp);
+            }
         }
     }
 
-    // no effect message if there are no monsters in the player's ring range
+    // no effect message if there are no monsters in the ring range
     if no_effect {
         game.messages.add("Fire ring caused no direct damage", RED);
     }
 
     // set tiles on fire with range of cast
-    set_tiles_on_fire(game, objects, RING_RANGE);
+    set_tiles_on_fire(game, center, radius, true);
     UseResult::UsedUp
 }
 
-/// Tiles can be set on fire, for example, after the Fire Ring spell is casted. The tiles
-/// eventually return to a normal state after 10 game ticks or if a monster steps on the tile that
-/// is on fire
+/// Throws a fireball at a targeted tile. Every fighter in the blast radius takes damage, the player
+/// included
 ///
-fn set_tiles_on_fire(game: &mut Game, objects: &mut [Object], range: i32) {
-    let pos = objects[PLAYER].pos();
-    for y in (-range)..range {
-        for x in pos.0..=(pos.0 + range) {
-            if game.map[x as usize][(pos.1 + y) as usize].perimeter
-                || game.map[x as usize][(pos.1 + y) as usize].blocked
-            {
-                break;
+fn cast_fireball(
+    inventory_id: usize,
+    frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let (damage, radius, range) = match game.inventory[inventory_id].item {
+        Some(Item::Fireball {
+            damage,
+            radius,
+            range,
+        }) => (damage, radius, range),
+        _ => return UseResult::Cancelled,
+    };
+
+    // ask the player for a target, starting on the closest monster if there is one
+    game.messages.add(
+        "Choose a target for the fireball with the arrows or mouse, Enter or left-click to cast, \
+         Esc or right-click to cancel",
+        LIGHT_CYAN,
+    );
+    let start = closest_monster(&game.fov, objects, range)
+        .map_or(objects[PLAYER].pos(), |monster_id| {
+            objects[monster_id].pos()
+        });
+    let (x, y) = match frontend.target_tile(game, objects, start, range) {
+        Some(target) => target,
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        format!(
+            "The fireball explodes, burning everything within {} tiles!",
+            radius
+        ),
+        ORANGE,
+    );
+
+    let mut xp_to_gain = 0;
+    for (id, object) in objects.iter_mut().enumerate() {
+        if object.distance(x, y) <= radius as f32 && object.fighter.is_some() {
+            game.messages.add(
+                format!("The {} gets burned for {} hit points", object.name, damage),
+                ORANGE,
+            );
+            if let Some(xp) = object.take_damage(damage, "a fireball", game) {
+                // the player gets no experience for killing themselves
+                if id != PLAYER {
+                    xp_to_gain += xp;
+                }
             }
-            game.map[x as usize][(pos.1 + y) as usize].on_fire = (true, 20_i32);
         }
-        for x in (pos.0 - range + 1)..pos.0 {
-            if game.map[x as usize][(pos.1 + y) as usize].perimeter
-                || game.map[x as usize][(pos.1 + y) as usize].blocked
-            {
-                break;
+    }
+    objects[PLAYER].gain_xp(xp_to_gain);
+    UseResult::UsedUp
+}
+
+/// Strikes a monster with a lightning bolt. The cursor starts on the closest monster in range
+///
+fn cast_lightning(
+    inventory_id: usize,
+    frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let (damage, range) = match game.inventory[inventory_id].item {
+        Some(Item::Lightning { damage, range }) => (damage, range),
+        _ => return UseResult::Cancelled,
+    };
+
+    let start = match closest_monster(&game.fov, objects, range) {
+        Some(monster_id) => objects[monster_id].pos(),
+        None => {
+            game.messages.add("No enemy is close enough to strike", RED);
+            return UseResult::Cancelled;
+        }
+    };
+    game.messages.add(
+        "Choose a monster to strike with the arrows or mouse, Enter or left-click to cast, Esc or \
+         right-click to cancel",
+        LIGHT_CYAN,
+    );
+    let target = match frontend.target_tile(game, objects, start, range) {
+        Some(target) => target,
+        None => return UseResult::Cancelled,
+    };
+    let monster_id = objects.iter().position(|object| {
+        object.pos() == target && object.fighter.is_some() && object.ai.is_some()
+    });
+    match monster_id {
+        Some(monster_id) => {
+            game.messages.add(
+                format!(
+                    "A lightning bolt strikes the {} with a loud thunder! The damage is {} hit \
+                     points",
+                    objects[monster_id].name, damage
+                ),
+                LIGHT_BLUE,
+            );
+            if let Some(xp) = objects[monster_id].take_damage(damage, "lightning", game) {
+                objects[PLAYER].gain_xp(xp);
             }
-            game.map[x as usize][(pos.1 + y) as usize].on_fire = (true, 20_i32);
+            UseResult::UsedUp
+        }
+        None => {
+            game.messages.add("There is no enemy there to strike", RED);
+            UseResult::Cancelled
         }
     }
 }
 
-/// Determines all the monsters that are in a certain range. They are returned as Option(fighters)
-/// in the vector
+/// Determines all the monsters in the player's FOV that are in a certain range of a center tile.
+/// They are returned as Option(fighters) in the vector
 ///
 fn get_monsters_in_range(
-    tcod: &mut Tcod,
-    objects: &mut [Object],
+    fov: &Fov,
+    objects: &[Object],
+    center: (i32, i32),
     range: i32,
 ) -> Vec<Option<usize>> {
     let mut monsters_in_range = vec![];
@@ -224,9 +607,9 @@ fn get_monsters_in_range(
         if (id != PLAYER)
             && object.fighter.is_some()
             && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
+            && fov.is_in_fov(object.x, object.y)
         {
-            let distance = objects[PLAYER].distance_to(object);
+            let distance = object.distance(center.0, center.1);
             if distance <= range as f32 {
                 monsters_in_range.push(Some(id));
             }
@@ -235,23 +618,100 @@ fn get_monsters_in_range(
     monsters_in_range
 }
 
+/// Returns the closest monster in the player's FOV within max_range of the player
+///
+fn closest_monster(fov: &Fov, objects: &[Object], max_range: f32) -> Option<usize> {
+    let mut closest_monster = None;
+    let mut closest_distance = max_range + 1.0;
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.ai.is_some()
+            && fov.is_in_fov(object.x, object.y)
+        {
+            let distance = objects[PLAYER].distance_to(object);
+            if distance < closest_distance {
+                closest_monster = Some(id);
+                closest_distance = distance;
+            }
+        }
+    }
+    closest_monster
+}
+
+/// Equips an item from the inventory, swapping out whatever is equipped in the same slot. Using
+/// an equipped item takes it off again
+///
+fn toggle_equipment(
+    inventory_id: usize,
+    _frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&mut game.messages);
+    } else {
+        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
+            game.inventory[current].dequip(&mut game.messages);
+        }
+        game.inventory[inventory_id].equip(&mut game.messages);
+    }
+
+    // taking off an item can lower the player's max HP below the current HP
+    let max_hp = objects[PLAYER].max_hp(game);
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.hp = cmp::min(fighter.hp, max_hp);
+    }
+    UseResult::UsedAndKept
+}
+
+/// Returns the inventory_id of the item equipped in a slot
+///
+fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    inventory.iter().position(|item| {
+        matches!(item.equipment, Some(equipment) if equipment.equipped && equipment.slot == slot)
+    })
+}
+
 /// Uses an item in inventory, and removes in from the inventory by inventory_id if sucessfully
 /// used. Cancelled otherwise.
 ///
-fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+fn use_item(
+    inventory_id: usize,
+    frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut [Object],
+) {
     use Item::*;
 
     if let Some(item) = game.inventory[inventory_id].item {
         let on_use = match item {
-            Heal => cast_heal,
-            FireRing => cast_fire_ring,
+            Heal { .. } => cast_heal,
+            FireRing { .. } => cast_fire_ring,
+            Fireball { .. } => cast_fireball,
+            Lightning { .. } => cast_lightning,
+            Potion(..) => cast_potion,
+            Equipment => toggle_equipment,
+            Key => cast_key,
+            Missile { .. } => cast_missile,
         };
 
-        match on_use(inventory_id, tcod, game, objects) {
+        match on_use(inventory_id, frontend, game, objects) {
             UseResult::UsedUp => {
-                // destroy after use
-                game.inventory.remove(inventory_id);
+                // destroy after use, one item at a time from a stack
+                let was_burdened = is_burdened(&game.inventory);
+                if game.inventory[inventory_id].count > 1 {
+                    game.inventory[inventory_id].count -= 1;
+                } else {
+                    game.inventory.remove(inventory_id);
+                }
+                report_burden(was_burdened, game);
             }
+            UseResult::UsedAndKept => {}
             UseResult::Cancelled => {
                 game.messages.add("Cancelled", WHITE);
             }
@@ -264,10 +724,24 @@ fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut
     }
 }
 
-/// Picks up an item. The inventory is capped at 26 items
+/// Picks up an item, adding it to the stack of identical items if there is one. The inventory is
+/// capped at MAX_MENU_OPTIONS stacks and MAX_CARRY_WEIGHT
 ///
 fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
-    if game.inventory.len() >= 26 {
+    let weight = carried_weight(&game.inventory) + objects[object_id].total_weight();
+    let stack_id = game
+        .inventory
+        .iter()
+        .position(|item| item.stacks_with(&objects[object_id]));
+    if weight > MAX_CARRY_WEIGHT {
+        game.messages.add(
+            format!(
+                "The {} is too heavy to carry, cannot pick it up",
+                objects[object_id].name
+            ),
+            RED,
+        );
+    } else if stack_id.is_none() && game.inventory.len() >= MAX_MENU_OPTIONS {
         game.messages.add(
             format!(
                 "Your inventory is full, cannot pick up {}",
@@ -276,46 +750,178 @@ fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
             RED,
         );
     } else {
+        let was_burdened = is_burdened(&game.inventory);
         let item = objects.swap_remove(object_id);
-        game.messages
-            .add(format!("You picked up a {}!", item.name), GREEN);
-        game.inventory.push(item);
+        let article = if item.count > 1 { "" } else { "a " };
+        game.messages.add(
+            format!("You picked up {}{}!", article, item.stack_name()),
+            GREEN,
+        );
+        match stack_id {
+            Some(stack_id) => game.inventory[stack_id].count += item.count,
+            None => game.inventory.push(item),
+        }
+        report_burden(was_burdened, game);
+    }
+}
+
+/// Drops a whole stack of items from the inventory on the player's tile, taking it off first if
+/// it is equipped
+///
+fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    let was_burdened = is_burdened(&game.inventory);
+    let mut item = game.inventory.remove(inventory_id);
+    item.dequip(&mut game.messages);
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    game.messages
+        .add(format!("You dropped the {}.", item.stack_name()), YELLOW);
+    objects.push(item);
+    report_burden(was_burdened, game);
+}
+
+/// Returns the total weight of the items in the inventory
+///
+fn carried_weight(inventory: &[Object]) -> i32 {
+    inventory.iter().map(Object::total_weight).sum()
+}
+
+/// Returns true if the player carries so much that they are slowed down
+///
+fn is_burdened(inventory: &[Object]) -> bool {
+    carried_weight(inventory) > BURDENED_WEIGHT
+}
+
+/// Tells the player when picking up or getting rid of items changed whether they are burdened
+///
+fn report_burden(was_burdened: bool, game: &mut Game) {
+    match (was_burdened, is_burdened(&game.inventory)) {
+        (false, true) => game
+            .messages
+            .add("You are burdened by your load and move slowly", ORANGE),
+        (true, false) => game.messages.add("You are no longer burdened", GREEN),
+        _ => {}
+    }
+}
+
+// A message with its text color. A message repeated straight away is counted instead of being
+// added again, and is shown with its count such as "goblin attacks player x3"
+//
+#[derive(Serialize, Deserialize)]
+struct Message {
+    text: String,
+    color: Color,
+    count: u32,
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.count > 1 {
+            write!(f, "{} x{}", self.text, self.count)
+        } else {
+            write!(f, "{}", self.text)
+        }
     }
 }
 
-// A message containing a string and text color for output
+// The message history. Only the newest MESSAGE_LIMIT messages are kept, the oldest are dropped
+// as new ones come in. Total counts every message ever added, repeats included, so a frontend
+// can tell which are new. Messages can also be mirrored to a log file as they are added
 //
+#[derive(Serialize, Deserialize)]
 struct Messages {
-    messages: Vec<(String, Color)>,
+    messages: VecDeque<Message>,
+    total: usize,
+    #[serde(skip)]
+    log: Option<File>,
 }
 
 impl Messages {
-    /// Creates a new vector to hold the messages
+    /// Creates an empty message history
     pub fn new() -> Self {
-        Self { messages: vec![] }
+        Self {
+            messages: VecDeque::new(),
+            total: 0,
+            log: None,
+        }
     }
-    /// Adds a new message and color
+    /// Adds a new message and color, or counts it again if it repeats the last message
     pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
+        let text = message.into();
+        self.write_log(&text);
+        self.total += 1;
+        match self.messages.back_mut() {
+            Some(last) if last.text == text && last.color == color => last.count += 1,
+            _ => {
+                if self.messages.len() == MESSAGE_LIMIT {
+                    self.messages.pop_front();
+                }
+                self.messages.push_back(Message {
+                    text,
+                    color,
+                    count: 1,
+                });
+            }
+        }
     }
-    /// iterator for messages
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+    /// iterator for messages, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
         self.messages.iter()
     }
+    /// Returns how many messages were ever added, repeats included
+    pub fn total(&self) -> usize {
+        self.total
+    }
+    /// Returns the text of the last n messages added, oldest first, with repeats written out in
+    /// full. Messages that are no longer kept are left out
+    pub fn latest(&self, n: usize) -> Vec<&str> {
+        let mut latest = vec![];
+        'messages: for message in self.messages.iter().rev() {
+            for _ in 0..message.count {
+                if latest.len() == n {
+                    break 'messages;
+                }
+                latest.push(message.text.as_str());
+            }
+        }
+        latest.reverse();
+        latest
+    }
+    /// Mirrors the messages to the file, one per line. The history kept so far is written first,
+    /// then every message as it is added
+    pub fn log_to(&mut self, mut file: File) -> io::Result<()> {
+        for text in self.latest(self.total) {
+            writeln!(file, "{}", text)?;
+        }
+        self.log = Some(file);
+        Ok(())
+    }
+    /// Writes a message to the log file. Logging stops if the file can no longer be written
+    fn write_log(&mut self, text: &str) {
+        if let Some(file) = self.log.as_mut() {
+            if let Err(e) = writeln!(file, "{}", text) {
+                eprintln!("rlt: stopped writing the message log: {}", e);
+                self.log = None;
+            }
+        }
+    }
 }
 
-// A Fighter is an object such as a monster or player that can attack, be attacked, and die
+// A Fighter is an object such as a monster or player that can attack, be attacked, and die. The
+// base stats do not include equipment bonuses. For the player xp is the experience gathered so
+// far, for a monster it is the experience it is worth
 //
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 struct Fighter {
-    max_hp: i32,
+    base_max_hp: i32,
     hp: i32,
-    defense: i32,
-    power: i32,
+    base_defense: i32,
+    base_power: i32,
+    xp: i32,
     on_death: DeathCallback,
+    on_hit: Option<StatusEffect>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 enum DeathCallback {
     Player,
     Monster,
@@ -333,31 +939,78 @@ impl DeathCallback {
 }
 
 fn player_death(player: &mut Object, game: &mut Game) {
-    // game over
-    game.messages.add("You Died!!", RED);
+    // game over, the seed is reported so the dungeon can be played again
+    game.messages
+        .add(format!("You Died!! (seed {})", game.seed), RED);
     player.char = '%';
     player.color = DARK_RED;
 }
 
 fn monster_death(monster: &mut Object, game: &mut Game) {
     // monster has died, and becomes an ASCII '%' on the tile where it was killed by the player
-    game.messages
-        .add(format!("{} is dead!", monster.name), ORANGE);
+    game.stats.kills += 1;
+    game.messages.add(
+        format!(
+            "{} is dead! It was worth {} experience points",
+            monster.name,
+            monster.fighter.map_or(0, |f| f.xp)
+        ),
+        ORANGE,
+    );
     monster.char = '%';
     monster.color = DARK_RED;
     monster.blocks = false;
     monster.fighter = None;
     monster.ai = None;
     monster.name = format!("remains of {}", monster.name);
-
-    // corpse position and tile update for corpse regen HP
-    let corpse = monster.pos();
-    game.map[corpse.0 as usize][corpse.1 as usize].has_corpse = true;
 }
 
-#[derive(Clone, Debug, PartialEq)]
+// How a monster acts on its turn. Each monster type picks one in RAWS_FILE
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Ai {
+    // Walks up to the player and attacks
     Basic,
+    // Stumbles about at random for num_turns, then goes back to its previous AI. The stumbling is
+    // the confusion status effect, so it stacks and shows like any other confusion
+    Confused {
+        previous_ai: Box<Ai>,
+        #[serde(deserialize_with = "positive")]
+        num_turns: i32,
+    },
+    // Fights like Basic, but runs from the player once its HP is down to below_hp
+    Fleeing {
+        #[serde(deserialize_with = "positive")]
+        below_hp: i32,
+    },
+    // Attacks from up to range tiles away and backs off when the player comes next to it
+    Ranged {
+        #[serde(deserialize_with = "positive")]
+        range: f32,
+    },
+    // Fights like Basic while it can see the player, and wanders about otherwise
+    Wanderer,
+}
+
+// What a monster knows about the player. It sees the player up to sight_radius tiles away if
+// nothing blocks the line between them. Once the player is out of sight it remembers where they
+// were last seen for memory_turns turns, then forgets them and goes idle
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Perception {
+    sight_radius: i32,
+    memory_turns: i32,
+    last_seen: Option<(i32, i32)>,
+    turns_unseen: i32,
+}
+
+impl Perception {
+    pub fn new(sight_radius: i32, memory_turns: i32) -> Self {
+        Perception {
+            sight_radius,
+            memory_turns,
+            last_seen: None,
+            turns_unseen: 0,
+        }
+    }
 }
 
 // The various tile states
@@ -367,10 +1020,15 @@ impl Tile {
             blocked: false,
             block_sight: false,
             perimeter: false,
-            teleport: false,
+            portal: None,
+            door: None,
+            trap: None,
+            grass: false,
+            stairs: false,
             explored: false,
-            has_corpse: false,
-            on_fire: (false, 0),
+            fire: 0,
+            lit_by_player: false,
+            smoke: 0,
         }
     }
 
@@ -379,10 +1037,15 @@ impl Tile {
             blocked: true,
             block_sight: true,
             perimeter: false,
-            teleport: false,
+            portal: None,
+            door: None,
+            trap: None,
+            grass: false,
+            stairs: false,
             explored: false,
-            has_corpse: false,
-            on_fire: (false, 0),
+            fire: 0,
+            lit_by_player: false,
+            smoke: 0,
         }
     }
     pub fn perimeter() -> Self {
@@ -390,28 +1053,50 @@ impl Tile {
             blocked: true,
             block_sight: true,
             perimeter: true,
-            teleport: false,
+            portal: None,
+            door: None,
+            trap: None,
+            grass: false,
+            stairs: false,
             explored: false,
-            has_corpse: false,
-            on_fire: (false, 0),
+            fire: 0,
+            lit_by_player: false,
+            smoke: 0,
         }
     }
-    pub fn teleport() -> Self {
+    pub fn stairs() -> Self {
         Tile {
             blocked: false,
             block_sight: false,
             perimeter: false,
-            teleport: true,
+            portal: None,
+            door: None,
+            trap: None,
+            grass: false,
+            stairs: true,
             explored: false,
-            has_corpse: false,
-            on_fire: (false, 0),
+            fire: 0,
+            lit_by_player: false,
+            smoke: 0,
         }
     }
-    pub fn is_teleportable_to(&self) -> bool {
-        !self.blocked && !self.teleport
+    pub fn door(locked: bool) -> Self {
+        Tile {
+            blocked: true,
+            block_sight: true,
+            door: Some(Door {
+                open: false,
+                locked,
+            }),
+            ..Tile::empty()
+        }
     }
-    pub fn is_empty(&self) -> bool {
-        !self.blocked
+    pub fn blocks_sight(&self) -> bool {
+        self.block_sight || self.smoke > 0
+    }
+    /// Returns true if monsters can get through the tile, opening it first if it is a closed door
+    pub fn is_passable(&self) -> bool {
+        !self.blocked || matches!(self.door, Some(door) if !door.locked)
     }
 }
 
@@ -467,7 +1152,7 @@ fn create_room(room: Room, map: &mut Map) {
 /// A basic object type which has coordinates and a ASCII char that represents it along with
 /// additional properties which give it added functionality if required
 ///
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Object {
     x: i32,
     y: i32,
@@ -476,9 +1161,16 @@ struct Object {
     name: String,
     blocks: bool,
     alive: bool,
+    level: i32,
     fighter: Option<Fighter>,
     ai: Option<Ai>,
     item: Option<Item>,
+    equipment: Option<Equipment>,
+    perception: Option<Perception>,
+    effects: Vec<StatusEffect>,
+    count: i32,
+    weight: i32,
+    corpse: Option<Corpse>,
 }
 
 impl Object {
@@ -491,18 +1183,19 @@ impl Object {
             name: name.into(),
             blocks,
             alive: false,
+            level: 1,
             fighter: None,
             ai: None,
             item: None,
+            equipment: None,
+            perception: None,
+            effects: vec![],
+            count: 1,
+            weight: 0,
+            corpse: None,
         }
     }
 
-    /// Draws the object to the screen with the correct color and coordinates
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
     /// Returns the current position of the object
     pub fn pos(&self) -> (i32, i32) {
         (self.x, self.y)
@@ -521,8 +1214,14 @@ impl Object {
         ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
     }
 
-    /// A Fighter Object takes an amount of damage
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) {
+    /// Returns the distance from the invoking object to a tile
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    /// A Fighter Object takes an amount of damage from the cause, such as "a goblin". Returns the
+    /// experience the Fighter was worth if the damage killed it
+    pub fn take_damage(&mut self, damage: i32, cause: &str, game: &mut Game) -> Option<i32> {
         // incur damage to health meter
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
@@ -532,71 +1231,256 @@ impl Object {
         if let Some(fighter) = self.fighter {
             if fighter.hp <= 0 {
                 self.alive = false;
+                self.effects.clear();
+                if self.is_player() {
+                    game.stats.cause_of_death = Some(cause.into());
+                }
                 fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
             }
         }
+        None
     }
-    /// Player regains hp from the corpse of a slain monster
-    pub fn consume_corpse(&mut self, hp: i32, game: &mut Game) {
-        // increase hp if possible
+    /// Adds experience to the invoking Fighter Object
+    pub fn gain_xp(&mut self, xp: i32) {
         if let Some(fighter) = self.fighter.as_mut() {
-            if fighter.hp + hp >= fighter.max_hp {
-                fighter.hp = fighter.max_hp;
-            } else {
-                fighter.hp += hp
-            }
+            fighter.xp += xp;
         }
-        game.messages.add(
-            format!("You consumed a corpse and gained {} HP", CORPSE_CONSUME_HP),
-            LIGHT_VIOLET,
-        );
     }
     /// Increases invoking objects HP by a specific amount
-    pub fn heal(&mut self, amount: i32) {
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
         if let Some(ref mut fighter) = self.fighter {
             fighter.hp += amount;
-            if fighter.hp > fighter.max_hp {
-                fighter.hp = fighter.max_hp;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
             }
         }
     }
-    /// Invoking object attacks another object
-    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-        // Damage formula for computing damage based on power and defense attributes
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
-        if damage > 0 {
-            // target takes an amount of damage
-            game.messages.add(
-                format!(
-                    "{} attacks {} for {} hit points",
-                    self.name, target.name, damage
-                ),
-                WHITE,
-            );
-            target.take_damage(damage, game);
+    /// Returns true if the invoking item can share a stack with the other item. Identical items
+    /// stack, equipment never does
+    pub fn stacks_with(&self, other: &Object) -> bool {
+        self.item.is_some()
+            && self.item == other.item
+            && self.name == other.name
+            && self.equipment.is_none()
+            && other.equipment.is_none()
+    }
+    /// Returns the weight of the invoking item, or of all the items in its stack
+    pub fn total_weight(&self) -> i32 {
+        self.weight * self.count
+    }
+    /// Returns the name of the invoking item with how many are in its stack
+    pub fn stack_name(&self) -> String {
+        if self.count > 1 {
+            format!("{} (x{})", self.name, self.count)
         } else {
-            game.messages.add(
-                format!(
-                    "{} attacks {} but it has not effect!",
-                    self.name, target.name
-                ),
-                WHITE,
-            );
+            self.name.clone()
         }
     }
-}
-
-/// Parses two elements from slice and mutably borrows
-///
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_as_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_as_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
+    /// Returns true if the invoking object is the player
+    pub fn is_player(&self) -> bool {
+        matches!(
+            self.fighter,
+            Some(Fighter {
+                on_death: DeathCallback::Player,
+                ..
+            })
+        )
     }
+    /// Returns the equipment the invoking object has equipped. Only the player wears equipment,
+    /// and it is kept in the inventory
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.is_player() {
+            game.inventory
+                .iter()
+                .filter_map(|item| item.equipment)
+                .filter(|equipment| equipment.equipped)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+    /// Returns the max HP including equipment bonuses
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|equipment| equipment.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+    /// Returns the attack power including equipment bonuses, less the player's hunger penalty
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|equipment| equipment.power_bonus)
+            .sum();
+        let hunger = if self.is_player() {
+            game.hunger().power_penalty()
+        } else {
+            0
+        };
+        base_power + bonus - hunger
+    }
+    /// Returns the defense including equipment bonuses
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|equipment| equipment.defense_bonus)
+            .sum();
+        base_defense + bonus
+    }
+    /// Equips the invoking item in its slot
+    pub fn equip(&mut self, messages: &mut Messages) {
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.add(
+                    format!("Equipped {} as your {}", self.name, equipment.slot),
+                    LIGHT_GREEN,
+                );
+            }
+        }
+    }
+    /// Takes the invoking item out of its slot
+    pub fn dequip(&mut self, messages: &mut Messages) {
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                equipment.equipped = false;
+                messages.add(
+                    format!("Took off {} from your {} slot", self.name, equipment.slot),
+                    LIGHT_YELLOW,
+                );
+            }
+        }
+    }
+    /// Returns the damage the invoking Fighter does to the target with each attack on average
+    pub fn expected_damage(&self, target: &Object, game: &Game) -> f32 {
+        expected_damage(self.power(game), target.defense(game))
+    }
+    /// Invoking object attacks another object. It rolls to hit against the target's evasion, then
+    /// rolls its damage dice, and the target's armour absorbs part of the damage. Every roll is
+    /// reported in the messages
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+        let attack = roll_attack(self.power(game), target.defense(game), &mut game.rng);
+        game.messages.add(
+            format!("{} attacks {}: {}", self.name, target.name, attack),
+            if attack.critical { LIGHT_RED } else { WHITE },
+        );
+        if !attack.hit {
+            return;
+        }
+        // target takes an amount of damage
+        let cause = format!("a {}", self.name);
+        if let Some(xp) = target.take_damage(attack.damage, &cause, game) {
+            self.gain_xp(xp);
+        }
+        // some attackers leave the target poisoned, burning or worse
+        if let Some(effect) = self.fighter.and_then(|f| f.on_hit) {
+            let effect = StatusEffect {
+                from_player: self.is_player(),
+                ..effect
+            };
+            apply_effect(target, effect, game);
+        }
+    }
+}
+
+/// Parses two elements from slice and mutably borrows
+///
+fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert!(first_index != second_index);
+    let split_as_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_as_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+/// Returns true if the coordinates are inside the map
+///
+fn in_map(x: i32, y: i32) -> bool {
+    (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y)
+}
+
+// The tiles the player can see. A ray is cast from the player to every tile on the edge of the
+// torch's reach and stops at the first tile that blocks sight, which is lit if FOV_LIGHT_WALLS
+#[derive(Debug, Default)]
+struct Fov {
+    visible: Vec<Vec<bool>>,
+}
+
+impl Fov {
+    /// Recomputes the visible tiles around the origin and marks them as explored on the map
+    pub fn compute(&mut self, map: &mut Map, origin: (i32, i32), radius: i32) {
+        self.visible = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        self.visible[origin.0 as usize][origin.1 as usize] = true;
+
+        let (min_x, max_x) = (origin.0 - radius, origin.0 + radius);
+        let (min_y, max_y) = (origin.1 - radius, origin.1 + radius);
+        let edge = (min_x..=max_x)
+            .flat_map(|x| vec![(x, min_y), (x, max_y)])
+            .chain((min_y..=max_y).flat_map(|y| vec![(min_x, y), (max_x, y)]));
+        for target in edge {
+            for (x, y) in line(origin, target) {
+                let (dx, dy) = (x - origin.0, y - origin.1);
+                if !in_map(x, y) || dx.pow(2) + dy.pow(2) > radius.pow(2) {
+                    break;
+                }
+                let blocks_sight = map[x as usize][y as usize].blocks_sight();
+                if !blocks_sight || FOV_LIGHT_WALLS {
+                    self.visible[x as usize][y as usize] = true;
+                }
+                if blocks_sight {
+                    break;
+                }
+            }
+        }
+
+        for (column, visible_column) in map.iter_mut().zip(&self.visible) {
+            for (tile, &visible) in column.iter_mut().zip(visible_column) {
+                tile.explored |= visible;
+            }
+        }
+    }
+
+    /// Returns true if the player can see the tile
+    pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        // nothing is visible before the first computation
+        in_map(x, y) && !self.visible.is_empty() && self.visible[x as usize][y as usize]
+    }
+}
+
+/// Returns the tiles on a straight line from one tile to another (Bresenham's algorithm). The
+/// starting tile is left out
+///
+fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dx + dy;
+    let (mut x, mut y) = from;
+    let mut tiles = vec![];
+    while (x, y) != to {
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if double_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x, y));
+    }
+    tiles
 }
 
 /// Returns true if the tile is blocking otherwise returns false
@@ -615,7 +1499,7 @@ fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
 
 /// Used for movement towards a specific location
 ///
-fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+fn move_towards(id: usize, target_x: i32, target_y: i32, map: &mut Map, objects: &mut [Object]) {
     // vector form this object to the target, and distance
     let dx = target_x - objects[id].x;
     let dy = target_y - objects[id].y;
@@ -626,99 +1510,163 @@ fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mu
     move_by(id, dx, dy, map, objects);
 }
 
-/// move by the given amount, if the destination is not blocked
+/// Moves an object one step along the shortest route to the target, around walls and other
+/// blocking objects, opening closed doors on the way. If there is no route it moves straight
+/// towards the target instead
 ///
-fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
+fn move_astar(id: usize, target: (i32, i32), map: &mut Map, objects: &mut [Object]) {
+    let step =
+        find_path(objects[id].pos(), target, map, objects).and_then(|path| path.first().copied());
+    match step {
+        Some((x, y)) if open_door(map, (x, y)) => {}
+        Some((x, y)) if !is_blocked(x, y, map, objects) && !known_trap(map, (x, y)) => {
+            objects[id].set_pos(x, y)
+        }
+        _ => move_towards(id, target.0, target.1, map, objects),
+    }
+}
+
+/// move by the given amount, if the destination is not blocked. A closed door in the way is
+/// opened instead, unless it is locked. Monsters don't step onto traps they know about
+///
+fn move_by(id: usize, dx: i32, dy: i32, map: &mut Map, objects: &mut [Object]) {
     let (x, y) = objects[id].pos();
+    if open_door(map, (x + dx, y + dy)) {
+        return;
+    }
+    if id != PLAYER && known_trap(map, (x + dx, y + dy)) {
+        return;
+    }
     if !is_blocked(x + dx, y + dy, map, objects) {
         objects[id].set_pos(x + dx, y + dy);
     }
 }
 
-/// Initial function to generate dungeon, spawn monsters, drop items, and place the player
-fn make_map(objects: &mut Vec<Object>) -> Map {
-    // fill map with "unblocked" tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    // create walled perimeter in game map
-    for tile in 0..MAP_WIDTH {
-        map[tile as usize][0_usize] = Tile::perimeter();
-        map[tile as usize][(MAP_HEIGHT - 1) as usize] = Tile::perimeter();
+/// Generates a dungeon level with the map style, or one picked at random if there is none. A
+/// level that fails validation is generated again, up to MAX_MAP_ATTEMPTS times, after which the
+/// last one is kept
+///
+fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    style: Option<MapStyle>,
+    raws: &Raws,
+    rng: &mut GameRng,
+) -> Map {
+    let style = style.unwrap_or_else(|| MapStyle::ALL[rng.gen_range(0, MapStyle::ALL.len())]);
+    let mut map = build_level(objects, level, style, raws, rng);
+    let mut attempts = 1;
+    while let Err(e) = validate_level(&map, objects) {
+        if attempts == MAX_MAP_ATTEMPTS {
+            eprintln!(
+                "rlt: keeping a level that failed validation {} times: {}",
+                attempts, e
+            );
+            break;
+        }
+        map = build_level(objects, level, style, raws, rng);
+        attempts += 1;
     }
-    for tile in 0..MAP_HEIGHT {
-        map[0_usize][tile as usize] = Tile::perimeter();
-        map[(MAP_WIDTH - 1) as usize][tile as usize] = Tile::perimeter();
+    map
+}
+
+/// Builds a dungeon level in the map style, joins any floor cut off from the start to the rest
+/// with extra corridors, then spawns monsters, drops items, places the player, puts doors in the
+/// room entrances and hides traps. Every object except the player is removed first so the
+/// function can build each new level
+///
+fn build_level(
+    objects: &mut Vec<Object>,
+    level: u32,
+    style: MapStyle,
+    raws: &Raws,
+    rng: &mut GameRng,
+) -> Map {
+    // only the player carries over from the previous level
+    objects.truncate(PLAYER + 1);
+
+    let BuiltMap {
+        mut map,
+        rooms,
+        start,
+        spawn_regions,
+    } = style.builder().build(rng);
+    join_cut_off_floor(&mut map, start, rng);
+    grow_grass(&mut map, rng);
+    objects[PLAYER].set_pos(start.0, start.1);
+
+    // Place the starting items in the vicinty of the player's starting position on the first level
+    if level == 1 {
+        let (x, y) = start;
+        let (x, y) = DIRECTIONS
+            .iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .find(|&(x, y)| !map[x as usize][y as usize].blocked)
+            .unwrap_or(start);
+        for name in &raws.starting_items {
+            if let Some(item) = raws.items.iter().find(|item| &item.name == name) {
+                objects.push(item.spawn(x, y));
+            }
+        }
     }
 
-    /* Testing for room functionality
-    let room1 = Room::new(20, 15, 10, 15);
-    let room2 = Room::new(50, 15, 10, 15);
-    let room3 = Room::new(115, 55, 5, 5);
-    create_room(room1, &mut map);
-    create_room(room2, &mut map);
-    create_room(room3, &mut map);
-    create_horizontal_passage(25, 55, 23, &mut map);
-    */
+    for region in &spawn_regions {
+        place_objects(region, &map, objects, level, raws, rng);
+    }
 
-    let mut rooms = vec![];
+    // The stairs and portals go in the centers of rooms, or of the spawn regions on maps without
+    // rooms. The stairs go down in a random one other than the player's
+    let mut spots: Vec<(i32, i32)> = if rooms.is_empty() {
+        spawn_regions
+            .iter()
+            .map(|region| region_center(region))
+            .collect()
+    } else {
+        rooms.iter().map(Room::center).collect()
+    };
+    spots.retain(|&spot| spot != start);
+    let stairs = if spots.is_empty() {
+        start
+    } else {
+        spots.swap_remove(rng.gen_range(0, spots.len()))
+    };
+    map[stairs.0 as usize][stairs.1 as usize] = Tile::stairs();
 
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
-        let new_room = Room::new(x, y, w, h);
+    // Put doors in the room entrances, locking some of them, before the portals so that no portal
+    // leads past a locked door
+    let key = raws.items.iter().find(|item| item.item == Item::Key);
+    place_doors(&mut map, &rooms, objects, key, rng);
 
-        let overlap = rooms
-            .iter()
-            .any(|other_room| new_room.room_overlaps(other_room));
-
-        if !overlap {
-            create_room(new_room, &mut map);
-            place_objects(new_room, &map, objects);
-            let (new_x, new_y) = new_room.center();
-            if !rooms.is_empty() {
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_horizontal_passage(prev_x, new_x, prev_y, &mut map);
-                    create_vertical_passage(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vertically, then horizontally
-                    create_vertical_passage(prev_y, new_y, prev_x, &mut map);
-                    create_horizontal_passage(prev_x, new_x, new_y, &mut map);
-                }
-            }
-            rooms.push(new_room);
-        }
-    }
-    // Get a random room and plae the player in it
-    let random_room_number = rand::thread_rng().gen_range(0, rooms.len());
-    let mut center: (i32, i32) = rooms[random_room_number].center();
-    objects[PLAYER].set_pos(center.0, center.1);
-
-    // Place a Fire Ring Scroll in the vicinty of the player's starting position
-    let mut object = Object::new(
-        objects[PLAYER].x + 1,
-        objects[PLAYER].y + 1,
-        '#',
-        "Fire Ring Scroll",
-        LIGHT_YELLOW,
-        false,
-    );
-    object.item = Some(Item::FireRing);
-    objects.push(object);
+    // Link the other spots with portals
+    place_portals(&mut map, spots, start, rng);
 
-    // Get a random room and place a teleport tile in it
-    let random_room_number = rand::thread_rng().gen_range(0, rooms.len());
-    center = rooms[random_room_number].center();
-    map[center.0 as usize][center.1 as usize] = Tile::teleport();
+    // Hide the traps last, so that none is put in a doorway or on a portal
+    place_traps(&mut map, objects, level, rng);
 
     map
 }
 
+/// Takes the player down the stairs to a newly generated level. The player's Fighter and the
+/// inventory are kept
+///
+fn next_level(game: &mut Game, objects: &mut Vec<Object>) {
+    game.dungeon_level += 1;
+    game.messages.add(
+        format!(
+            "You descend deeper into the dungeon to level {}",
+            game.dungeon_level
+        ),
+        VIOLET,
+    );
+    game.map = make_map(
+        objects,
+        game.dungeon_level,
+        game.map_style,
+        &game.raws,
+        &mut game.rng,
+    );
+}
+
 /// Creats a horizontal passage to from x1 to x2 at y on y-axis
 ///
 fn create_horizontal_passage(x1: i32, x2: i32, y: i32, map: &mut Map) {
@@ -750,227 +1698,28 @@ fn create_vertical_passage(y1: i32, y2: i32, x: i32, map: &mut Map) {
     }
 }
 
-/// main function to render the game state, objects in FOV, and map
-fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool) {
-    if fov_recompute {
-        // If necessary FOV needs to be updated
-        let player = &objects[PLAYER];
-        tcod.fov
-            .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-    }
-
-    // Set Tile background colors with pattern matching
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            let visible = tcod.fov.is_in_fov(x, y);
-            let wall = game.map[x as usize][y as usize].block_sight;
-            let perimeter = game.map[x as usize][y as usize].perimeter;
-            let teleport = game.map[x as usize][y as usize].teleport;
-            let on_fire = game.map[x as usize][y as usize].on_fire;
-
-            // Check if Tile is on fire. Decrement count if still on fire. If count is 0, then
-            // update Tile's status
-            if on_fire.0 {
-                if game.map[x as usize][y as usize].on_fire.1 == 0 {
-                    game.map[x as usize][y as usize].on_fire.0 = false;
-                } else {
-                    game.map[x as usize][y as usize].on_fire.1 -= 1;
-                }
-            }
-
-            let color = match (visible, wall, perimeter, teleport, on_fire.0) {
-                // Outside player's FOV
-                (false, true, true, false, false) => COLOR_DARK_PERIMETER,
-                (false, true, false, false, false) => COLOR_DARK_WALL,
-                (false, false, false, true, false) => COLOR_DARK_TELEPORT,
-                (false, false, false, false, false) => COLOR_DARK_GROUND,
-                // Inside player's FOV
-                (true, true, true, false, false) => COLOR_LIGHT_PERIMETER,
-                (true, true, false, false, false) => COLOR_LIGHT_WALL,
-                (true, false, false, true, false) => COLOR_LIGHT_TELEPORT,
-                (true, false, false, false, false) => COLOR_DARK_GROUND,
-                (_, _, _, _, true) => LIGHTER_RED,
-                _ => COLOR_DARK_PERIMETER,
-            };
-
-            // Explored and unexplored tiles update
-            let explored = &mut game.map[x as usize][y as usize].explored;
-            if visible {
-                *explored = true;
-            }
-            if *explored {
-                tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set);
-            }
-        }
-    }
-    let mut to_draw: Vec<_> = objects
-        .iter()
-        .filter(|o| tcod.fov.is_in_fov(o.x, o.y))
-        .collect();
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
-    for object in &to_draw {
-        object.draw(&mut tcod.con);
-    }
-
-    // blit is a special tcod function to push the contents of "con" to the root console
-    blit(
-        &tcod.con,
-        (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
-        &mut tcod.root,
-        (0, 0),
-        1.0,
-        1.0,
-    );
-
-    tcod.panel.set_default_background(BLACK);
-    tcod.panel.clear();
-
-    // Print messages to UI panel
-    let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.messages.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
-        y -= msg_height;
-        if y < 0 {
-            break;
-        }
-        tcod.panel.set_default_foreground(color);
-        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
-    }
-
-    // Render the player's attributes (health meter)
-    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
-    let max_hp = objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
-    render_bar(
-        &mut tcod.panel,
-        1,
-        1,
-        BAR_WIDTH,
-        "HP",
-        hp,
-        max_hp,
-        LIGHT_RED,
-        DARKER_RED,
-    );
-
-    // Output the panel with blit
-    blit(
-        &tcod.panel,
-        (0, 0),
-        (SCREEN_WIDTH, PANEL_HEIGHT),
-        &mut tcod.root,
-        (0, PANEL_Y),
-        1.0,
-        1.0,
-    );
-}
-
-/// Checks if a the player is a on a Tile that teleports. Changes player's position if true,
-/// otherwise there is no effect
-fn check_teleport(map: &mut Map, player: &mut Object) {
-    // Check if player is on a teleport tile
-    if map[player.x as usize][player.y as usize].teleport {
-        let prev = (player.x, player.y);
-        // Randomly selects a tiles, checks if its suitable to teleport to and changes
-        // player's current location to that tile when found
-        loop {
-            let x = rand::thread_rng().gen_range(1, MAP_WIDTH - 1);
-            let y = rand::thread_rng().gen_range(1, MAP_HEIGHT - 1);
-            if map[x as usize][y as usize].is_teleportable_to() {
-                player.x = x;
-                player.y = y;
-                map[prev.0 as usize][prev.1 as usize].teleport = false;
-                break;
-            }
-        }
-        place_rand_teleport_tile(map);
-    }
-}
-
-/// Returns the coordinates of a randomly generated map Tile that is not in a corridor or
-/// blocking the entrance to or exit from a corridor
+/// Carries out a command from the player. A dead player can only exit
 ///
-fn get_tile_non_passage_blocking(map: &Map) -> (i32, i32) {
-    // Get random (x,y) coord for a tile
-    let mut x: i32 = rand::thread_rng().gen_range(3, MAP_WIDTH - 3);
-    let mut y: i32 = rand::thread_rng().gen_range(3, MAP_HEIGHT - 3);
+fn play_command(
+    command: Command,
+    frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+) -> PlayerAction {
+    use PlayerAction::*;
 
-    // Check if random gen (x,y) is blocking a passage
-    loop {
-        if map[(x + 1) as usize][y as usize].is_empty()
-            & map[(x - 1) as usize][y as usize].is_empty()
-            & map[x as usize][(y + 1) as usize].is_empty()
-            & map[x as usize][(y - 1) as usize].is_empty()
-            & map[(x + 1) as usize][(y + 1) as usize].is_empty()
-            & map[(x + 1) as usize][(y - 1) as usize].is_empty()
-            & map[(x - 1) as usize][(y + 1) as usize].is_empty()
-            & map[(x - 1) as usize][(y - 1) as usize].is_empty()
-        {
-            break;
-        } else {
-            x = rand::thread_rng().gen_range(3, MAP_WIDTH - 3);
-            y = rand::thread_rng().gen_range(3, MAP_HEIGHT - 3);
-        }
+    if command == Command::Exit {
+        return Exit;
     }
-    (x, y)
-}
-
-/// Places a randomly generated teleport Tile in the game map
-///
-fn place_rand_teleport_tile(map: &mut Map) {
-    loop {
-        let tile: (i32, i32) = get_tile_non_passage_blocking(map);
-        if map[tile.0 as usize][tile.1 as usize].is_teleportable_to() {
-            map[tile.0 as usize][tile.1 as usize].teleport = true;
-            break;
-        }
+    if !objects[PLAYER].alive {
+        return DidntTakeTurn;
     }
-}
-
-/// Key controls for player movement and gameplay
-fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
-    use tcod::input::Key;
-    use tcod::input::KeyCode::*;
-    use PlayerAction::*;
-
-    let key = tcod.root.wait_for_keypress(true);
-    let player_alive = objects[PLAYER].alive;
-    match (key, key.text(), player_alive) {
-        (
-            Key {
-                code: Enter,
-                alt: true,
-                ..
-            },
-            _,
-            _,
-        ) => {
-            // Alt+Enter: toggle fullscreen
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
-            DidntTakeTurn
-        }
-        (Key { code: Escape, .. }, _, _) => Exit, // exit game
-
-        // movement keys
-        (Key { code: Up, .. }, _, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: Down, .. }, _, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: Left, .. }, _, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Right, .. }, _, true) => {
-            player_move_or_attack(1, 0, game, objects);
+    match command {
+        Command::Move(dx, dy) => {
+            player_move_or_attack(dx, dy, game, objects);
             TookTurn
         }
-        (Key { code: Shift, .. }, _, true) => {
+        Command::PickUp => {
             // pick up item
             let position = objects[PLAYER].pos();
             let item_id = objects
@@ -979,99 +1728,100 @@ fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> P
             if let Some(item_id) = item_id {
                 pick_item_up(item_id, game, objects);
             }
-            if game.map[position.0 as usize][position.1 as usize].has_corpse {
-                objects[PLAYER].consume_corpse(CORPSE_CONSUME_HP, game);
-                game.map[position.0 as usize][position.1 as usize].has_corpse = false;
-                for object in objects {
-                    if object.pos() == (position.0, position.1) {
-                        object.char = '_';
-                    }
-                }
+            if let Some(corpse_id) = corpse_at(objects, position) {
+                eat_corpse(corpse_id, game, objects);
             }
             DidntTakeTurn
         }
-        (Key { code: Tab, .. }, _, true) => {
-            let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next to an item to use it, or any other to cancel.\n",
-                &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, objects);
+        Command::Descend => {
+            // go down the stairs if the player is standing on them
+            let (x, y) = objects[PLAYER].pos();
+            if game.map[x as usize][y as usize].stairs {
+                next_level(game, objects);
+            } else {
+                game.messages.add("There are no stairs down here", WHITE);
             }
             DidntTakeTurn
         }
-        _ => DidntTakeTurn,
-    }
-}
-
-/// tcod menu setup primarly reused from tcodlib menu tutorial  
-fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
-    assert!(
-        options.len() <= 26,
-        "Cannot have a menu with more than 26 options."
-    );
-
-    let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
-    let height = options.len() as i32 + header_height;
-
-    let mut window = Offscreen::new(width, height);
-
-    window.set_default_foreground(WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
-
-    // print all the options
-    for (index, option_text) in options.iter().enumerate() {
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("({}) {}", menu_letter, option_text.as_ref());
-        window.print_ex(
-            0,
-            header_height + index as i32,
-            BackgroundFlag::None,
-            TextAlignment::Left,
-            text,
-        );
-    }
-
-    // blit the contents of "window" to the root console
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
-    blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
-
-    // present the root console to the player and wait for a key-press
-    root.flush();
-    let key = root.wait_for_keypress(true);
-
-    // convert the ASCII code to an index; if it corresponds to an option, return it
-    if key.printable.is_alphabetic() {
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
-        } else {
-            None
+        Command::UseItem(inventory_index) => {
+            if inventory_index < game.inventory.len() {
+                use_item(inventory_index, frontend, game, objects);
+            }
+            DidntTakeTurn
         }
-    } else {
-        None
+        Command::DropItem(inventory_index) => {
+            if inventory_index < game.inventory.len() {
+                drop_item(inventory_index, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Command::ThrowItem(inventory_index) => {
+            if inventory_index < game.inventory.len()
+                && throw_item(inventory_index, frontend, game, objects)
+            {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Command::CloseDoor(dx, dy) => {
+            let (x, y) = objects[PLAYER].pos();
+            if close_door(game, objects, (x + dx, y + dy)) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Command::Search => {
+            if !spot_traps(game, &objects[PLAYER], true) {
+                game.messages.add("You search but find nothing", WHITE);
+            }
+            TookTurn
+        }
+        Command::DisarmTrap(dx, dy) => {
+            let (x, y) = objects[PLAYER].pos();
+            if disarm_trap(game, objects, (x + dx, y + dy)) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Command::Exit => Exit,
     }
 }
 
-fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
+/// Shows a menu with each item of the inventory as an option and returns the chosen inventory_id
+///
+fn inventory_menu(inventory: &[Object], header: &str, input: &mut dyn Input) -> Option<usize> {
     // how a menu with each item of the inventory as an option
     let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
-        inventory.iter().map(|item| item.name.clone()).collect()
+        inventory
+            .iter()
+            .map(|item| match item.equipment {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (equipped as {})", item.name, equipment.slot)
+                }
+                _ => item.stack_name(),
+            })
+            .collect()
     };
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+    // show how much the player carries under the header
+    let weight = carried_weight(inventory);
+    let header = format!(
+        "{}Weight: {}/{}{}\n",
+        header,
+        weight,
+        BURDENED_WEIGHT,
+        if weight > BURDENED_WEIGHT {
+            " (burdened)"
+        } else {
+            ""
+        }
+    );
+    let inventory_index = input.menu(&header, &options, INVENTORY_WIDTH);
 
     // if an item was chosen, return it
     if !inventory.is_empty() {
@@ -1083,6 +1833,13 @@ fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option
 
 /// Determines if a player is moving or attacking based on game state
 fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+    // A confused player stumbles in a random direction instead
+    let (dx, dy) = if has_effect(&objects[PLAYER], Effect::Confusion) {
+        DIRECTIONS[game.rng.gen_range(0, DIRECTIONS.len())]
+    } else {
+        (dx, dy)
+    };
+
     // The coords to where the player is moving to
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
@@ -1099,259 +1856,1195 @@ fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Objec
             let (player, target) = mut_two(PLAYER, target_id, objects);
             player.attack(target, game);
         }
-        // If no Fighter found then move to this tile
+        // If no Fighter found then open the door there or move to this tile, setting off any trap
+        // in it
         None => {
-            move_by(PLAYER, dx, dy, &game.map, objects);
+            if !player_open_door(game, (x, y)) {
+                move_by(PLAYER, dx, dy, &mut game.map, objects);
+                if objects[PLAYER].pos() == (x, y) {
+                    spring_trap(PLAYER, game, objects);
+                }
+            }
         }
     }
 }
 
-/// Spawns monsters throughout the dungeon
+// A value that applies from a dungeon level onwards, used to scale spawns with depth
+//
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Transition {
+    level: u32,
+    value: u32,
+}
+
+/// Returns the value that applies at the given level. The table is sorted by level
 ///
-fn place_objects(room: Room, map: &Map, objects: &mut Vec<Object>) {
-    let num_monsters = rand::thread_rng().gen_range(0, MAX_ROOM_MONSTERS + 1);
+fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
 
-    for _ in 0..num_monsters {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        if !is_blocked(x, y, map, objects) {
-            let mut monster = if rand::random::<f32>() < 0.8 {
-                // Create a goblin
-                let mut goblin = Object::new(x, y, 'G', "goblin", DESATURATED_GREEN, true);
-                goblin.fighter = Some(Fighter {
-                    max_hp: 9,
-                    hp: 9,
-                    defense: 2,
-                    power: 3,
-                    on_death: DeathCallback::Monster,
-                });
-                goblin.ai = Some(Ai::Basic);
-                goblin
-            } else if rand::random::<f32>() < 0.5 {
-                // Create a drudge
-                let mut drudge = Object::new(x, y, 'D', "drudge", DARKER_RED, true);
-                drudge.fighter = Some(Fighter {
-                    max_hp: 3,
-                    hp: 3,
-                    defense: 1,
-                    power: 2,
-                    on_death: DeathCallback::Monster,
-                });
-                drudge.ai = Some(Ai::Basic);
-                drudge
-            } else {
-                // Create a white rabbit
-                let mut white_rabbit = Object::new(x, y, 'W', "white rabbit", WHITE, true);
-                white_rabbit.fighter = Some(Fighter {
-                    max_hp: 50,
-                    hp: 50,
-                    defense: 2,
-                    power: 5,
-                    on_death: DeathCallback::Monster,
-                });
-                white_rabbit.ai = Some(Ai::Basic);
-                white_rabbit
-            };
-            monster.alive = true;
-            objects.push(monster);
-        }
-    }
+// Monster and item definitions loaded from RAWS_FILE, so the game can be balanced without
+// recompiling
+//
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Raws {
+    #[serde(deserialize_with = "spawn_table")]
+    max_room_monsters: Vec<Transition>,
+    #[serde(deserialize_with = "spawn_table")]
+    max_room_items: Vec<Transition>,
+    starting_items: Vec<String>,
+    monsters: Vec<MonsterRaw>,
+    items: Vec<ItemRaw>,
+}
 
-    let num_items = rand::thread_rng().gen_range(0, MAX_ROOM_ITEMS + 1);
+// A monster type with its stats, looks, AI and senses, and how likely it is to spawn on each
+// level. effects are the status effects it spawns with, such as confusion for a few turns
+//
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonsterRaw {
+    name: String,
+    glyph: char,
+    color: Color,
+    #[serde(deserialize_with = "positive")]
+    hp: i32,
+    defense: i32,
+    power: i32,
+    xp: i32,
+    ai: Ai,
+    #[serde(deserialize_with = "positive")]
+    sight_radius: i32,
+    #[serde(deserialize_with = "positive")]
+    memory_turns: i32,
+    #[serde(default)]
+    attack_effect: Option<StatusEffect>,
+    #[serde(default)]
+    effects: Vec<StatusEffect>,
+    corpse: Corpse,
+    #[serde(deserialize_with = "spawn_table")]
+    spawn_weight: Vec<Transition>,
+}
 
-    for _ in 0..num_items {
-        let mut x: i32 = 0;
-        let mut y: i32 = 0;
-        // Find a random spot for this items
-        let mut again: bool = true;
-        while again {
-            x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-            y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-            if !is_blocked(x, y, map, objects) {
-                again = false;
-            }
-        }
+// An item type with its effect, looks, and how likely it is to spawn on each level
+//
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemRaw {
+    name: String,
+    glyph: char,
+    color: Color,
+    item: Item,
+    equipment: Option<Equipment>,
+    #[serde(deserialize_with = "positive")]
+    weight: i32,
+    #[serde(deserialize_with = "spawn_table")]
+    spawn_weight: Vec<Transition>,
+}
 
-        // Randomly place item pick-ups
-        let dice = rand::random::<f32>();
-        let item = if dice < 0.6 {
-            // A Healing Potion
-            let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
-            object.item = Some(Item::Heal);
-            object
-        } else {
-            // A Fire Ring Scroll
-            let mut object = Object::new(x, y, '#', "Fire ring spell", LIGHT_YELLOW, false);
-            object.item = Some(Item::FireRing);
-            object
-        };
-        objects.push(item);
+impl MonsterRaw {
+    /// Creates a monster of this type at the given coordinates
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter {
+            base_max_hp: self.hp,
+            hp: self.hp,
+            base_defense: self.defense,
+            base_power: self.power,
+            xp: self.xp,
+            on_death: DeathCallback::Monster,
+            on_hit: self.attack_effect,
+        });
+        monster.ai = Some(self.ai.clone());
+        monster.effects = self.effects.clone();
+        monster.perception = Some(Perception::new(self.sight_radius, self.memory_turns));
+        monster.corpse = Some(self.corpse);
+        monster.alive = true;
+        monster
     }
 }
 
-fn render_bar(
-    panel: &mut Offscreen,
-    x: i32,
-    y: i32,
-    total_width: i32,
-    name: &str,
-    value: i32,
-    maximum: i32,
-    bar_color: Color,
-    back_color: Color,
-) {
-    // Compute bar width
-    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
-
-    // Set the background
-    panel.set_default_background(back_color);
-    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
-
-    panel.set_default_background(bar_color);
-    if bar_width > 0 {
-        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
-    }
-
-    // Set the foreground and print the text on top
-    panel.set_default_foreground(WHITE);
-    panel.print_ex(
-        x + total_width / 2,
-        y,
-        BackgroundFlag::None,
-        TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),
-    );
+impl ItemRaw {
+    /// Creates an item of this type at the given coordinates
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut object = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        object.item = Some(self.item);
+        object.equipment = self.equipment;
+        object.weight = self.weight;
+        object
+    }
 }
 
-/// Allows the Fighter Object to take a turn which is either an attack or a movement
+/// Deserializes a number that has to be above zero
 ///
-fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // Object Fighter moves towards the PLAYER
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            // Object Fighter attacks the PLAYER
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
-        }
-    }
-    // Checks if the Tile the Fighter is currently standing on is on fire and if so deals damage to
-    // that Figher
-    let tile_state = game.map[monster_x as usize][monster_y as usize].on_fire;
-    if tile_state.0 {
-        let fire_damage = match tile_state.1 {
-            1..=5 => 1,
-            _ => 2,
-        };
-        objects[monster_id].take_damage(fire_damage, game);
-        game.messages.add(
-            format!(
-                "Your smoldering fire ring singed the {} for {} HP",
-                objects[monster_id].name, fire_damage
-            ),
-            LIGHT_BLUE,
-        );
-        // Tile cools down to normal after dealing damage to a monster
-        game.map[monster_x as usize][monster_y as usize].on_fire.0 = false;
+fn positive<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::Deserialize<'de> + Default + PartialOrd + std::fmt::Display,
+{
+    let value = T::deserialize(deserializer)?;
+    if value <= T::default() {
+        return Err(de::Error::custom(format!(
+            "expected a number above 0, found {}",
+            value
+        )));
     }
+    Ok(value)
 }
 
-/// Main game loop for testing
+/// Deserializes a table of Transitions, which has to be sorted by level starting from level 1
 ///
-fn main() {
-    tcod::system::set_fps(LIMIT_FPS);
-
-    // Root setup
-    let root = Root::initializer()
-        .font("arial10x10.png", FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("Rust/libtcod tutorial")
-        .init();
-
-    // Tcod struct setup
-    let mut tcod = Tcod {
-        root,
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-    };
-
-    // Create the PLAYER
+fn spawn_table<'de, D>(deserializer: D) -> Result<Vec<Transition>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let table = Vec::<Transition>::deserialize(deserializer)?;
+    let mut previous_level = 0;
+    for transition in &table {
+        if transition.level <= previous_level {
+            return Err(de::Error::custom(format!(
+                "levels must start at 1 and go up, found level {} after level {}",
+                transition.level, previous_level
+            )));
+        }
+        previous_level = transition.level;
+    }
+    Ok(table)
+}
+
+/// Loads the raws from RAWS_FILE. Errors in the file are reported with their line and column,
+/// errors between entries are reported with the line of the entry
+///
+fn load_raws() -> Result<Raws, Box<dyn Error>> {
+    let mut raws_data = String::new();
+    let mut file =
+        File::open(RAWS_FILE).map_err(|e| format!("Could not open {}: {}", RAWS_FILE, e))?;
+    file.read_to_string(&mut raws_data)?;
+    let raws: Raws =
+        serde_json::from_str(&raws_data).map_err(|e| format!("{}: {}", RAWS_FILE, e))?;
+    check_raws(&raws, &raws_data)?;
+    Ok(raws)
+}
+
+/// Checks what serde can't between the entries of the raws: names are unique, equipment comes
+/// with its equipment entry and the starting items are defined. Errors are reported as
+/// "raws.json:<line>: ..." with the line of the entry, found in the source
+///
+fn check_raws(raws: &Raws, source: &str) -> Result<(), String> {
+    let error = |offset: Option<usize>, message: String| {
+        let line = offset.map_or(0, |offset| source[..offset].matches('\n').count() + 1);
+        format!("{}:{}: {}", RAWS_FILE, line, message)
+    };
+
+    let mut names = HashSet::new();
+    for name in raws
+        .monsters
+        .iter()
+        .map(|monster| &monster.name)
+        .chain(raws.items.iter().map(|item| &item.name))
+    {
+        if !names.insert(name) {
+            return Err(error(
+                find_string(source, "\"name\"", name, 1),
+                format!("\"{}\" is defined more than once", name),
+            ));
+        }
+    }
+    for item in &raws.items {
+        if (item.item == Item::Equipment) != item.equipment.is_some() {
+            return Err(error(
+                find_string(source, "\"name\"", &item.name, 0),
+                format!(
+                    "\"{}\" needs both \"item\": \"Equipment\" and an \"equipment\" entry",
+                    item.name
+                ),
+            ));
+        }
+    }
+    for name in &raws.starting_items {
+        if !raws.items.iter().any(|item| &item.name == name) {
+            return Err(error(
+                find_string(source, "\"starting_items\"", name, 0),
+                format!("starting item \"{}\" is not defined in items", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the offset in the JSON source of the nth time (counting from 0) the string value comes
+/// after the key, as the key's value or in the key's list. None if it isn't there
+///
+fn find_string(source: &str, key: &str, value: &str, nth: usize) -> Option<usize> {
+    let quoted = serde_json::to_string(value).ok()?;
+    source
+        .match_indices(key)
+        .filter_map(|(offset, _)| {
+            // skip to the key's value, and past the start of a list
+            let after = offset + key.len();
+            let rest = source[after..].trim_start().strip_prefix(':')?;
+            let value_offset = source.len() - rest.trim_start().len();
+            let found = if rest.trim_start().starts_with('[') {
+                let end = source[value_offset..].find(']')? + value_offset;
+                source[value_offset..end].find(&quoted)? + value_offset
+            } else if rest.trim_start().starts_with(&quoted) {
+                value_offset
+            } else {
+                return None;
+            };
+            Some(found)
+        })
+        .nth(nth)
+}
+
+/// Spawns monsters and items from the raws on the floor tiles of a spawn region. Which ones and how
+/// many depends on the level
+///
+fn place_objects(
+    region: &[(i32, i32)],
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    raws: &Raws,
+    rng: &mut GameRng,
+) {
+    // monster random table
+    let num_monsters = rng.gen_range(0, from_dungeon_level(&raws.max_room_monsters, level) + 1);
+    let monster_chances: &mut Vec<_> = &mut raws
+        .monsters
+        .iter()
+        .enumerate()
+        .map(|(index, monster)| Weighted {
+            weight: from_dungeon_level(&monster.spawn_weight, level),
+            item: index,
+        })
+        .collect();
+    let can_spawn_monsters = monster_chances.iter().any(|chance| chance.weight > 0);
+
+    for _ in 0..num_monsters {
+        let (x, y) = region[rng.gen_range(0, region.len())];
+
+        if can_spawn_monsters && !is_blocked(x, y, map, objects) {
+            let monster_choice = WeightedChoice::new(monster_chances);
+            let index = monster_choice.ind_sample(rng);
+            objects.push(raws.monsters[index].spawn(x, y));
+        }
+    }
+
+    // item random table
+    let num_items = rng.gen_range(0, from_dungeon_level(&raws.max_room_items, level) + 1);
+    let item_chances: &mut Vec<_> = &mut raws
+        .items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| Weighted {
+            weight: from_dungeon_level(&item.spawn_weight, level),
+            item: index,
+        })
+        .collect();
+    if !item_chances.iter().any(|chance| chance.weight > 0) {
+        return;
+    }
+
+    for _ in 0..num_items {
+        // Find a random free spot for this item
+        let free: Vec<(i32, i32)> = region
+            .iter()
+            .copied()
+            .filter(|&(x, y)| !is_blocked(x, y, map, objects))
+            .collect();
+        if free.is_empty() {
+            return;
+        }
+        let (x, y) = free[rng.gen_range(0, free.len())];
+
+        // Randomly place item pick-ups
+        let item_choice = WeightedChoice::new(item_chances);
+        let index = item_choice.ind_sample(rng);
+        objects.push(raws.items[index].spawn(x, y));
+    }
+}
+
+/// Returns the experience needed to advance from the given level to the next one
+///
+fn level_up_xp(level: i32) -> i32 {
+    LEVEL_UP_BASE + level * LEVEL_UP_FACTOR
+}
+
+/// Levels the player up once enough experience has been gathered. The player picks which Fighter
+/// stat to raise from a menu that cannot be cancelled
+///
+fn level_up(frontend: &mut dyn Frontend, game: &mut Game, objects: &mut [Object]) {
+    let player = &mut objects[PLAYER];
+    let level_up_xp = level_up_xp(player.level);
+    if player.fighter.map_or(0, |f| f.xp) < level_up_xp {
+        return;
+    }
+
+    player.level += 1;
+    game.messages.add(
+        format!(
+            "Your battle skills grow stronger! You reached level {}!",
+            player.level
+        ),
+        YELLOW,
+    );
+
+    // keep asking until a choice is made. If the input ends first the level-up is left for when
+    // the game is continued
+    let fighter = player.fighter.as_mut().unwrap();
+    let options = [
+        format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+        format!("Strength (+1 attack, from {})", fighter.base_power),
+        format!("Agility (+1 defense, from {})", fighter.base_defense),
+    ];
+    let mut choice = None;
+    while choice.is_none() {
+        if frontend.closed() {
+            player.level -= 1;
+            return;
+        }
+        choice = frontend.menu(
+            "Level up! Choose a stat to raise:\n",
+            &options,
+            LEVEL_SCREEN_WIDTH,
+        );
+    }
+    fighter.xp -= level_up_xp;
+    match choice.unwrap() {
+        0 => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
+        }
+        1 => {
+            fighter.base_power += 1;
+        }
+        2 => {
+            fighter.base_defense += 1;
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Allows the Fighter Object to take a turn which is either an attack or a movement. What it does
+/// depends on its AI, which may change for the next turn
+///
+fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    use Ai::*;
+
+    update_perception(monster_id, game, objects);
+    let start = objects[monster_id].pos();
+    // a confused monster stumbles about whatever its AI
+    if has_effect(&objects[monster_id], Effect::Confusion) {
+        random_step(monster_id, game, objects);
+    } else if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Basic => ai_basic(monster_id, game, objects),
+            Confused {
+                previous_ai,
+                num_turns,
+            } => ai_confused(monster_id, game, objects, *previous_ai, num_turns),
+            Fleeing { below_hp } => ai_fleeing(monster_id, game, objects, below_hp),
+            Ranged { range } => ai_ranged(monster_id, game, objects, range),
+            Wanderer => ai_wanderer(monster_id, game, objects),
+        };
+        objects[monster_id].ai = Some(new_ai);
+    }
+    // a monster that stepped onto a trap sets it off
+    if objects[monster_id].pos() != start {
+        spring_trap(monster_id, game, objects);
+    }
+}
+
+/// Returns true if nothing that blocks sight lies between the two tiles
+///
+fn line_of_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    line(from, to)
+        .into_iter()
+        .take_while(|&tile| tile != to)
+        .all(|(x, y)| !map[x as usize][y as usize].blocks_sight())
+}
+
+/// Updates what the monster knows about the player at the start of its turn
+///
+fn update_perception(monster_id: usize, game: &Game, objects: &mut [Object]) {
+    let player_pos = objects[PLAYER].pos();
+    let monster = &objects[monster_id];
+    let mut perception = match monster.perception {
+        Some(perception) => perception,
+        None => return,
+    };
+    let sees = objects[PLAYER].alive
+        && monster.distance(player_pos.0, player_pos.1) <= perception.sight_radius as f32
+        && line_of_sight(&game.map, monster.pos(), player_pos);
+    if sees {
+        perception.last_seen = Some(player_pos);
+        perception.turns_unseen = 0;
+    } else if perception.last_seen.is_some() {
+        perception.turns_unseen += 1;
+        if perception.turns_unseen > perception.memory_turns {
+            perception.last_seen = None;
+        }
+    }
+    objects[monster_id].perception = Some(perception);
+}
+
+/// Returns true if the monster can see the player this turn
+///
+fn sees_player(monster_id: usize, objects: &[Object]) -> bool {
+    matches!(
+        objects[monster_id].perception,
+        Some(Perception {
+            last_seen: Some(_),
+            turns_unseen: 0,
+            ..
+        })
+    )
+}
+
+/// Searches for a player the monster has lost sight of by heading to where they were last seen
+/// and looking around there. Returns false if the monster does not remember the player
+///
+fn search_for_player(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> bool {
+    let last_seen = match objects[monster_id].perception {
+        Some(perception) => perception.last_seen,
+        None => None,
+    };
+    match last_seen {
+        Some(last_seen) if objects[monster_id].pos() != last_seen => {
+            move_astar(monster_id, last_seen, &mut game.map, objects);
+            true
+        }
+        Some(_) => {
+            random_step(monster_id, game, objects);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The monster attacks the player, if the player is still alive
+///
+fn attack_player(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+        let (monster, player) = mut_two(monster_id, PLAYER, objects);
+        monster.attack(player, game);
+    }
+}
+
+/// The monster shoots at the player along a clear line of fire. The shot rolls to hit like a blow
+///
+fn shoot_player(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    if game
+        .fov
+        .is_in_fov(objects[monster_id].x, objects[monster_id].y)
+    {
+        game.messages.add(
+            format!("The {} shoots at you", objects[monster_id].name),
+            ORANGE,
+        );
+    }
+    attack_player(monster_id, game, objects);
+}
+
+/// Moves the monster one step in a random direction, if that tile is free
+///
+fn random_step(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    let dx = game.rng.gen_range(-1, 2);
+    let dy = game.rng.gen_range(-1, 2);
+    move_by(monster_id, dx, dy, &mut game.map, objects);
+}
+
+/// Moves the monster to the free tile next to it that is furthest from the player. Returns false
+/// if no step takes it further away
+///
+fn step_away(monster_id: usize, game: &Game, objects: &mut [Object]) -> bool {
+    let (x, y) = objects[monster_id].pos();
+    let mut best = None;
+    let mut best_distance = objects[PLAYER].distance(x, y);
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            let (next_x, next_y) = (x + dx, y + dy);
+            if (dx, dy) == (0, 0)
+                || is_blocked(next_x, next_y, &game.map, objects)
+                || known_trap(&game.map, (next_x, next_y))
+            {
+                continue;
+            }
+            let distance = objects[PLAYER].distance(next_x, next_y);
+            if distance > best_distance {
+                best = Some((next_x, next_y));
+                best_distance = distance;
+            }
+        }
+    }
+    match best {
+        Some((next_x, next_y)) => {
+            objects[monster_id].set_pos(next_x, next_y);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Walks up to the player, around anything in the way, and attacks once next to them. A player
+/// that got away is searched for
+///
+fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    if sees_player(monster_id, objects) {
+        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+            let player_pos = objects[PLAYER].pos();
+            move_astar(monster_id, player_pos, &mut game.map, objects);
+        } else {
+            attack_player(monster_id, game, objects);
+        }
+    } else {
+        search_for_player(monster_id, game, objects);
+    }
+    Ai::Basic
+}
+
+/// Confuses the monster for num_turns and takes its first stumbling step. The confusion effect
+/// keeps it stumbling from then on, and it gets its previous AI back once the effect wears off
+///
+fn ai_confused(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    previous_ai: Ai,
+    num_turns: i32,
+) -> Ai {
+    let confusion = StatusEffect {
+        kind: Effect::Confusion,
+        turns: num_turns,
+        strength: 1,
+        from_player: false,
+    };
+    apply_effect(&mut objects[monster_id], confusion, game);
+    random_step(monster_id, game, objects);
+    previous_ai
+}
+
+/// Fights like a basic monster until its HP is down to below_hp, then runs from the player and
+/// stays put once out of sight. A cornered monster still fights back
+///
+fn ai_fleeing(monster_id: usize, game: &mut Game, objects: &mut [Object], below_hp: i32) -> Ai {
+    let hp = objects[monster_id].fighter.map_or(0, |f| f.hp);
+    if hp > below_hp {
+        ai_basic(monster_id, game, objects);
+    } else if sees_player(monster_id, objects)
+        && !step_away(monster_id, game, objects)
+        && objects[monster_id].distance_to(&objects[PLAYER]) < 2.0
+    {
+        attack_player(monster_id, game, objects);
+    }
+    Ai::Fleeing { below_hp }
+}
+
+/// Shoots at the player from up to range tiles away. It closes in when the player is further than
+/// that or something stands in the line of fire, and backs off when the player comes next to it,
+/// fighting back if it cannot
+///
+fn ai_ranged(monster_id: usize, game: &mut Game, objects: &mut [Object], range: f32) -> Ai {
+    if sees_player(monster_id, objects) {
+        let distance = objects[monster_id].distance_to(&objects[PLAYER]);
+        let (_, hit) = flight(
+            objects[monster_id].pos(),
+            objects[PLAYER].pos(),
+            &game.map,
+            objects,
+        );
+        if distance < 2.0 {
+            if !step_away(monster_id, game, objects) {
+                attack_player(monster_id, game, objects);
+            }
+        } else if distance <= range && hit == Some(PLAYER) {
+            shoot_player(monster_id, game, objects);
+        } else {
+            let player_pos = objects[PLAYER].pos();
+            move_astar(monster_id, player_pos, &mut game.map, objects);
+        }
+    } else {
+        search_for_player(monster_id, game, objects);
+    }
+    Ai::Ranged { range }
+}
+
+/// Fights like a basic monster while it can see or remembers the player, and wanders about
+/// otherwise
+///
+fn ai_wanderer(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    if sees_player(monster_id, objects) {
+        ai_basic(monster_id, game, objects);
+    } else if !search_for_player(monster_id, game, objects) {
+        random_step(monster_id, game, objects);
+    }
+    Ai::Wanderer
+}
+
+/// Writes the game state and all objects to SAVE_FILE, tagged with SAVE_VERSION
+///
+fn save_game(game: &Game, objects: &[Object]) -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&(SAVE_VERSION, game, objects))?;
+    let mut file = File::create(SAVE_FILE)?;
+    file.write_all(save_data.as_bytes())?;
+    Ok(())
+}
+
+/// Reads the game written by save_game. A file from another version or a file that cannot be
+/// parsed returns an error describing the problem
+///
+fn load_game() -> Result<(Game, Vec<Object>), Box<dyn Error>> {
+    let mut save_data = String::new();
+    let mut file = File::open(SAVE_FILE)?;
+    file.read_to_string(&mut save_data)?;
+    decode_save(&save_data)
+}
+
+/// Decodes the game and objects from the contents of a save file, checking that everything the
+/// game indexes the map with is inside it
+///
+fn decode_save(save_data: &str) -> Result<(Game, Vec<Object>), Box<dyn Error>> {
+    // Check the version before decoding the rest so an old format is not reported as corrupted
+    let value: serde_json::Value = serde_json::from_str(save_data)
+        .map_err(|e| format!("The save file is corrupted ({})", e))?;
+    match value.get(0).and_then(|version| version.as_u64()) {
+        Some(version) if version == u64::from(SAVE_VERSION) => {}
+        Some(version) => {
+            return Err(format!(
+                "The save file is from version {}, this game reads version {}",
+                version, SAVE_VERSION
+            )
+            .into())
+        }
+        None => return Err("The save file is corrupted (no version found)".into()),
+    }
+    let (_, game, objects): (u32, Game, Vec<Object>) =
+        serde_json::from_value(value).map_err(|e| format!("The save file is corrupted ({})", e))?;
+
+    // A well formed file can still describe a map or objects the game would index out of bounds
+    let map_ok = game.map.len() == MAP_WIDTH as usize
+        && game
+            .map
+            .iter()
+            .all(|column| column.len() == MAP_HEIGHT as usize);
+    let objects_ok = !objects.is_empty() && objects.iter().all(|object| in_map(object.x, object.y));
+    let portals_ok = map_ok
+        && game
+            .map
+            .iter()
+            .flatten()
+            .filter_map(|tile| tile.portal)
+            .all(|portal| in_map(portal.destination.0, portal.destination.1));
+    if !map_ok || !objects_ok || !portals_ok {
+        return Err("The save file is corrupted (map, objects or portals out of bounds)".into());
+    }
+    Ok((game, objects))
+}
+
+/// Shows a message in a menu with no options and waits for a key-press
+///
+fn msgbox(text: &str, width: i32, input: &mut dyn Input) {
+    input.menu(text, &[], width);
+}
+
+/// Creates the player and a freshly generated dungeon
+///
+fn new_game(raws: Raws, seed: u64, map_style: Option<MapStyle>) -> (Game, Vec<Object>) {
+    // Create the PLAYER
     let mut player = Object::new(0, 0, '@', "player", WHITE, true);
     player.alive = true;
     player.fighter = Some(Fighter {
-        max_hp: 40,
+        base_max_hp: 40,
         hp: 40,
-        defense: 2,
-        power: 5,
+        base_defense: 2,
+        base_power: 5,
+        xp: 0,
         on_death: DeathCallback::Player,
+        on_hit: None,
     });
 
     // Vector for all game objects
     let mut objects = vec![player];
 
     // Game struct with map, messages, inventory
+    let mut rng = GameRng::new(seed);
     let mut game = Game {
-        map: make_map(&mut objects),
+        map: make_map(&mut objects, 1, map_style, &raws, &mut rng),
         messages: Messages::new(),
         inventory: vec![],
+        dungeon_level: 1,
+        map_style,
+        food: START_FOOD,
+        stats: Stats::default(),
+        seed,
+        rng,
+        raws,
+        fov: Fov::default(),
     };
 
-    // Initial map setup
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !game.map[x as usize][y as usize].block_sight,
-                !game.map[x as usize][y as usize].blocked,
-            );
+    // Welcome message
+    game.messages.add("Welcome to Roguelike!", BLUE);
+
+    (game, objects)
+}
+
+/// Shows the title menu until the player starts a new game or continues the saved game, which
+/// takes the raws. The first new game is played with the seed if one was given, later ones get a
+/// random seed. New games are generated in the map style if there is one. Returns None if the
+/// player quits
+///
+fn main_menu(
+    raws: &mut Raws,
+    seed: &mut Option<u64>,
+    map_style: Option<MapStyle>,
+    frontend: &mut dyn Frontend,
+) -> Option<(Game, Vec<Object>)> {
+    // the choices on the title menu, Continue only when there is a saved game
+    enum Choice {
+        NewGame,
+        Continue,
+        Quit,
+    }
+
+    loop {
+        let mut choices = vec![("New game", Choice::NewGame)];
+        if Path::new(SAVE_FILE).exists() {
+            choices.push(("Continue", Choice::Continue));
+        }
+        choices.push(("Quit", Choice::Quit));
+        let options: Vec<String> = choices.iter().map(|(name, _)| name.to_string()).collect();
+
+        frontend.clear();
+        let choice = frontend.menu("ROGUELIKE IN RUST\n\n", &options, MAIN_MENU_WIDTH);
+        if frontend.closed() {
+            return None;
+        }
+        match choice.map(|index| &choices[index].1) {
+            Some(Choice::NewGame) => {
+                let seed = seed.take().unwrap_or_else(rand::random);
+                return Some(new_game(std::mem::take(raws), seed, map_style));
+            }
+            Some(Choice::Continue) => match load_game() {
+                Ok((mut game, objects)) => {
+                    game.raws = std::mem::take(raws);
+                    game.messages.add("Welcome back to Roguelike!", BLUE);
+                    return Some((game, objects));
+                }
+                Err(e) => msgbox(
+                    &format!("Could not load the saved game:\n{}\n", e),
+                    INVENTORY_WIDTH,
+                    frontend,
+                ),
+            },
+            Some(Choice::Quit) => return None,
+            None => {}
         }
     }
+}
 
-    // Initialize PLAYER's previous position for later use
-    let mut previous_player_position = (-1, -1);
+/// Shows how the run ended: what killed the player, how long they survived, how many monsters
+/// died and how deep they got
+///
+fn game_over(game: &Game, objects: &[Object], input: &mut dyn Input) {
+    let cause = game
+        .stats
+        .cause_of_death
+        .as_deref()
+        .unwrap_or("unknown causes");
+    msgbox(
+        &format!(
+            "GAME OVER\n\nKilled by {} on dungeon level {}.\n\nTurns survived: {}\n\
+             Monsters killed: {}\nPlayer level: {}\nSeed: {}\n\nPress any key to continue.",
+            cause,
+            game.dungeon_level,
+            game.stats.turns,
+            game.stats.kills,
+            objects[PLAYER].level,
+            game.seed
+        ),
+        INVENTORY_WIDTH,
+        input,
+    );
+}
 
-    // Welcome message
-    game.messages.add("Welcome to Roguelike!", BLUE);
+// The command-line options
+#[derive(Debug, Default)]
+struct Options {
+    seed: Option<u64>,
+    headless: bool,
+    record: Option<String>,
+    replay: Option<String>,
+    stop_at: Option<usize>,
+    log: Option<String>,
+    map_style: Option<MapStyle>,
+}
 
-    // Game loop
-    while !tcod.root.window_closed() {
-        // Clear previous frame
-        tcod.con.clear();
+/// Parses the command-line options, "--seed <number>", "--headless", "--record <file>",
+/// "--replay <file>" with an optional "--stop-at <turn>", "--log <file>", and "--map <style>"
+///
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("--seed needs a number, found \"{}\"", value))?;
+                options.seed = Some(value);
+            }
+            "--headless" => options.headless = true,
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file")?),
+            "--replay" => options.replay = Some(args.next().ok_or("--replay needs a file")?),
+            "--log" => options.log = Some(args.next().ok_or("--log needs a file")?),
+            "--map" => {
+                let value = args.next().ok_or("--map needs a map style")?;
+                let value = value.parse().map_err(|e| format!("--map: {}", e))?;
+                options.map_style = Some(value);
+            }
+            "--stop-at" => {
+                let value = args.next().ok_or("--stop-at needs a turn")?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("--stop-at needs a turn, found \"{}\"", value))?;
+                options.stop_at = Some(value);
+            }
+            _ => return Err(format!("unknown option \"{}\"", arg)),
+        }
+    }
+    if options.replay.is_some()
+        && (options.seed.is_some() || options.record.is_some() || options.map_style.is_some())
+    {
+        return Err("--replay cannot be used with --seed, --record or --map".into());
+    }
+    if options.stop_at.is_some() && options.replay.is_none() {
+        return Err("--stop-at can only be used with --replay".into());
+    }
+    Ok(options)
+}
 
+/// Recomputes what the player can see from where they are standing
+///
+fn update_fov(game: &mut Game, objects: &[Object]) {
+    game.fov
+        .compute(&mut game.map, objects[PLAYER].pos(), TORCH_RADIUS);
+}
+
+/// Runs the game until the player exits. The frontend draws the game and supplies the player's
+/// commands, all of the turn resolution happens here
+///
+fn play_game(frontend: &mut dyn Frontend, game: &mut Game, objects: &mut Vec<Object>) {
+    update_fov(game, objects);
+
+    loop {
         // Render the current game state
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos());
-        render_all(&mut tcod, &mut game, &objects, fov_recompute);
-        tcod.root.flush();
+        frontend.render(game, objects);
 
-        // Handle PLAYER keys for movement and attacking
-        previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(&mut tcod, &mut game, &mut objects);
+        // Carry out the PLAYER's command for movement, attacking, and items
+        let command = match frontend.next_command(game, objects) {
+            Some(command) => command,
+            None => continue,
+        };
+        let player_action = play_command(command, frontend, game, objects);
         if player_action == PlayerAction::Exit {
             break;
         }
+        update_fov(game, objects);
+        if player_action == PlayerAction::TookTurn {
+            game.stats.turns += 1;
+            // The player may spot hidden traps nearby every turn
+            spot_traps(game, &objects[PLAYER], false);
+        }
 
-        // Monster turn
+        // Monster turn, then the fire spreads and burns down, status effects tick, the player
+        // gets hungrier and corpses rot. A burdened player is slow, and everything else gets two
+        // turns for each of theirs. A player who faints from hunger loses a turn on top
         if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, &tcod, &mut game, &mut objects);
+            let turns = if is_burdened(&game.inventory) { 2 } else { 1 };
+            let turns = if faints(game) { turns * 2 } else { turns };
+            for _ in 0..turns {
+                if !objects[PLAYER].alive {
+                    break;
                 }
+                for id in 0..objects.len() {
+                    if objects[id].ai.is_some() {
+                        ai_take_turn(id, game, objects);
+                    }
+                }
+                step_fire(game, objects);
+                tick_effects(game, objects);
+                tick_portals(&mut game.map);
+                tick_hunger(game, objects);
+                tick_corpses(game, objects);
             }
         }
 
         // Ensures that the PLAYER is a '@' if still alive
         if objects[PLAYER].alive {
             objects[PLAYER].char = '@';
+            level_up(frontend, game, objects);
+        }
+
+        // Take the PLAYER and monsters standing on portals through them
+        use_portals(game, objects);
+        update_fov(game, objects);
+
+        // The game is over once the PLAYER dies, show how it ended
+        if !objects[PLAYER].alive {
+            frontend.render(game, objects);
+            break;
+        }
+    }
+}
+
+/// Opens the message log file if one was asked for
+///
+fn open_log(options: &Options) -> Option<File> {
+    let path = options.log.as_ref()?;
+    match File::create(path) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("rlt: could not write the message log {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Mirrors the game's messages to the log file. Every game played in one run of the program
+/// writes to the same file
+///
+fn start_log(log: Option<&File>, game: &mut Game) {
+    if let Some(log) = log {
+        if let Err(e) = log.try_clone().and_then(|file| game.messages.log_to(file)) {
+            eprintln!("rlt: could not write the message log: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parses the options and runs the game in the tcod window, or headless if asked for
+///
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!(
+                "rlt: {}\nusage: rlt [--headless] [--seed <number>] [--map <style>] \
+                 [--record <file>] [--log <file>]\n       \
+                 rlt [--headless] --replay <file> [--stop-at <turn>] [--log <file>]",
+                e
+            );
+            std::process::exit(2);
+        }
+    };
+
+    let mut frontend: Box<dyn Frontend> = if options.headless {
+        Box::new(HeadlessFrontend::new(io::stdin().lock(), io::stdout()))
+    } else {
+        Box::new(TcodFrontend::new())
+    };
+
+    // Load the monster and item definitions, the game cannot start without them
+    let raws = match load_raws() {
+        Ok(raws) => raws,
+        Err(e) => {
+            eprintln!("{}", e);
+            if !options.headless {
+                msgbox(
+                    &format!("{}\n\nPress any key to exit.", e),
+                    INVENTORY_WIDTH,
+                    frontend.as_mut(),
+                );
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let log = open_log(&options);
+
+    // A replay plays the recorded game back and is not saved
+    if let Some(path) = &options.replay {
+        let replay = match Replay::load(path) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("rlt: could not load the replay {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        let (mut game, mut objects) = new_game(raws, replay.seed, replay.map_style);
+        start_log(log.as_ref(), &mut game);
+        let mut player = ReplayPlayer::new(replay, options.stop_at, frontend.as_mut());
+        play_game(&mut player, &mut game, &mut objects);
+        if !objects[PLAYER].alive {
+            game_over(&game, &objects, frontend.as_mut());
+        }
+        return;
+    }
+
+    // Headless and recorded games are a single new game, as a replay starts from the seed.
+    // Otherwise the title menu is shown before every game, so the player can start again after
+    // dying or continue the game saved on exit
+    let single_game = options.headless || options.record.is_some();
+    let mut raws = raws;
+    let mut seed = options.seed;
+    loop {
+        let started = if single_game {
+            let seed = seed.take().unwrap_or_else(rand::random);
+            Some(new_game(std::mem::take(&mut raws), seed, options.map_style))
+        } else {
+            main_menu(&mut raws, &mut seed, options.map_style, frontend.as_mut())
+        };
+        let (mut game, mut objects) = match started {
+            Some(started) => started,
+            None => break,
+        };
+        start_log(log.as_ref(), &mut game);
+
+        match &options.record {
+            Some(path) => match Recorder::create(path, &game, frontend.as_mut()) {
+                Ok(mut recorder) => play_game(&mut recorder, &mut game, &mut objects),
+                Err(e) => {
+                    eprintln!("rlt: could not record the replay {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            },
+            None => play_game(frontend.as_mut(), &mut game, &mut objects),
         }
 
-        // Check if PLAYER has moved to a teleporting Tile on game map
-        check_teleport(&mut game.map, &mut objects[PLAYER]);
+        // Save the game so it can be continued. A dead player has nothing left to continue.
+        // Headless games are not saved
+        if objects[PLAYER].alive {
+            if !options.headless {
+                if let Err(e) = save_game(&game, &objects) {
+                    msgbox(
+                        &format!("Could not save the game:\n{}\n", e),
+                        INVENTORY_WIDTH,
+                        frontend.as_mut(),
+                    );
+                }
+            }
+        } else {
+            if !options.headless && Path::new(SAVE_FILE).exists() {
+                let _ = fs::remove_file(SAVE_FILE);
+            }
+            game_over(&game, &objects, frontend.as_mut());
+        }
+
+        if single_game || frontend.closed() {
+            break;
+        }
+        raws = std::mem::take(&mut game.raws);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A raws file with a healing potion and a sword, laid out one entry per line
+    const RAWS: &str = r#"{
+"max_room_monsters": [], "max_room_items": [],
+"starting_items": ["potion"],
+"monsters": [],
+"items": [
+{"name": "potion", "glyph": "!", "color": {"r": 1, "g": 1, "b": 1}, "item": {"Heal": {"amount": 1}}, "weight": 1, "spawn_weight": []},
+{"name": "sword", "glyph": "/", "color": {"r": 1, "g": 1, "b": 1}, "item": "Equipment", "equipment": {"slot": "Weapon", "max_hp_bonus": 0, "power_bonus": 1, "defense_bonus": 0}, "weight": 1, "spawn_weight": []}
+]
+}"#;
+
+    /// Returns the map of a new game with the seed, portal destinations included, and where every
+    /// object on it is
+    fn generate(seed: u64) -> (String, Vec<(String, i32, i32)>) {
+        let (game, objects) = new_game(load_raws().unwrap(), seed, None);
+        let positions = objects
+            .iter()
+            .map(|object| (object.name.clone(), object.x, object.y))
+            .collect();
+        (serde_json::to_string(&game.map).unwrap(), positions)
+    }
+
+    /// Returns what decoding a save of the game reports, after the portal at (1, 1) is pointed at
+    /// the destination
+    fn decode_with_portal(destination: (i32, i32)) -> Result<(), String> {
+        let (mut game, objects) = new_game(load_raws().unwrap(), 1, None);
+        game.map[1][1].portal = Some(Portal {
+            destination,
+            cooldown: 0,
+            closed_turns: 0,
+            one_shot: true,
+        });
+        let save_data = serde_json::to_string(&(SAVE_VERSION, &game, &objects)).unwrap();
+        decode_save(&save_data)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parses the raws in the source and returns what checking them reports
+    fn check(source: &str) -> Result<(), String> {
+        let raws: Raws = serde_json::from_str(source).unwrap();
+        check_raws(&raws, source)
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_dungeon() {
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+
+    #[test]
+    fn save_with_portal_out_of_the_map_is_corrupt() {
+        assert_eq!(decode_with_portal((2, 2)), Ok(()));
+        for &destination in &[(MAP_WIDTH, 2), (2, -1)] {
+            assert_eq!(
+                decode_with_portal(destination),
+                Err("The save file is corrupted (map, objects or portals out of bounds)".into())
+            );
+        }
+    }
+
+    #[test]
+    fn valid_raws_pass() {
+        assert_eq!(check(RAWS), Ok(()));
+    }
+
+    #[test]
+    fn duplicate_name_reports_the_second_entry() {
+        let source = RAWS.replace("\"sword\"", "\"potion\"");
+        assert_eq!(
+            check(&source),
+            Err("raws.json:7: \"potion\" is defined more than once".into())
+        );
+    }
+
+    #[test]
+    fn equipment_without_entry_reports_its_line() {
+        let source = RAWS.replace(
+            "\"item\": {\"Heal\": {\"amount\": 1}}",
+            "\"item\": \"Equipment\"",
+        );
+        assert_eq!(
+            check(&source),
+            Err(
+                "raws.json:6: \"potion\" needs both \"item\": \"Equipment\" and an \"equipment\" \
+                 entry"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_starting_item_reports_its_line() {
+        let source = RAWS.replace("[\"potion\"]", "[\"potion\", \"shield\"]");
+        assert_eq!(
+            check(&source),
+            Err("raws.json:3: starting item \"shield\" is not defined in items".into())
+        );
     }
 }
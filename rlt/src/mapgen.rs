@@ -0,0 +1,573 @@
+use super::*;
+
+// BSP leaves are split until they are smaller than twice this in both directions
+const BSP_MIN_LEAF: i32 = 10;
+
+// Caves start as random noise with this chance out of 100 of a wall on each tile, which is then
+// smoothed this many times
+const CAVE_WALL_CHANCE: u32 = 45;
+const CAVE_SMOOTHING_STEPS: usize = 5;
+
+// The drunkard's walk carves until this percentage of the map is floor, each walker taking at most
+// this many steps
+const DRUNKARD_FLOOR_PERCENT: usize = 40;
+const DRUNKARD_STEPS: usize = 400;
+
+// Every level has GRASS_PATCHES patches of grass, each covering the floor within GRASS_RADIUS
+// tiles of its middle with GRASS_CHANCE out of 100 for each tile
+const GRASS_PATCHES: usize = 4;
+const GRASS_RADIUS: i32 = 3;
+const GRASS_CHANCE: u32 = 70;
+
+// Maps without rooms are cut into regions of this size to spawn monsters and items in. Regions
+// with fewer floor tiles than the minimum are left empty
+const REGION_WIDTH: i32 = 16;
+const REGION_HEIGHT: i32 = 11;
+const MIN_REGION_TILES: usize = 10;
+
+// The ways a level can be generated, chosen with --map or picked at random for every level
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MapStyle {
+    Rooms,
+    Bsp,
+    Caves,
+    Drunkard,
+}
+
+// What a map builder hands back: the carved map, its rooms (none for caves), where the player
+// starts, and the floor tiles of each region monsters and items are spawned in. Maps with rooms
+// have one region per room, in the same order
+pub struct BuiltMap {
+    pub map: Map,
+    pub rooms: Vec<Room>,
+    pub start: (i32, i32),
+    pub spawn_regions: Vec<Vec<(i32, i32)>>,
+}
+
+// Generates the layout of a level. Builders only carve inside the perimeter, and every floor tile
+// they leave can be reached from the start
+pub trait MapBuilder {
+    fn build(&self, rng: &mut GameRng) -> BuiltMap;
+}
+
+// The original generator: rooms scattered at random, each joined to the one before by a corridor
+struct RoomsBuilder;
+
+// Binary space partitioning: the map is split in two again and again, and every piece gets a room
+struct BspBuilder;
+
+// Cellular automata: random noise smoothed into caves
+struct CaveBuilder;
+
+// Drunkard's walk: walkers stumble about from the middle of the map, digging as they go
+struct DrunkardBuilder;
+
+impl MapStyle {
+    pub const ALL: [MapStyle; 4] = [
+        MapStyle::Rooms,
+        MapStyle::Bsp,
+        MapStyle::Caves,
+        MapStyle::Drunkard,
+    ];
+
+    /// Returns the builder for this style
+    pub fn builder(self) -> Box<dyn MapBuilder> {
+        match self {
+            MapStyle::Rooms => Box::new(RoomsBuilder),
+            MapStyle::Bsp => Box::new(BspBuilder),
+            MapStyle::Caves => Box::new(CaveBuilder),
+            MapStyle::Drunkard => Box::new(DrunkardBuilder),
+        }
+    }
+}
+
+impl std::fmt::Display for MapStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            MapStyle::Rooms => write!(f, "rooms"),
+            MapStyle::Bsp => write!(f, "bsp"),
+            MapStyle::Caves => write!(f, "caves"),
+            MapStyle::Drunkard => write!(f, "drunkard"),
+        }
+    }
+}
+
+impl std::str::FromStr for MapStyle {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        MapStyle::ALL
+            .iter()
+            .copied()
+            .find(|style| style.to_string() == name)
+            .ok_or_else(|| {
+                let names: Vec<String> = MapStyle::ALL.iter().map(|s| s.to_string()).collect();
+                format!("expected one of {}, found \"{}\"", names.join(", "), name)
+            })
+    }
+}
+
+impl MapBuilder for RoomsBuilder {
+    fn build(&self, rng: &mut GameRng) -> BuiltMap {
+        let mut map = new_map();
+        let mut rooms: Vec<Room> = vec![];
+
+        for _ in 0..MAX_ROOMS {
+            // random width and height
+            let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            // random position without going out of the boundaries of the map
+            let x = rng.gen_range(0, MAP_WIDTH - w);
+            let y = rng.gen_range(0, MAP_HEIGHT - h);
+            let new_room = Room::new(x, y, w, h);
+
+            let overlap = rooms
+                .iter()
+                .any(|other_room| new_room.room_overlaps(other_room));
+
+            if !overlap {
+                create_room(new_room, &mut map);
+                if let Some(prev_room) = rooms.last() {
+                    connect_rooms(*prev_room, new_room, &mut map, rng);
+                }
+                rooms.push(new_room);
+            }
+        }
+        rooms_map(map, rooms, rng)
+    }
+}
+
+impl MapBuilder for BspBuilder {
+    fn build(&self, rng: &mut GameRng) -> BuiltMap {
+        let mut map = new_map();
+        let mut leaves = vec![];
+        split_leaf(
+            Room {
+                x1: 0,
+                y1: 0,
+                x2: MAP_WIDTH - 1,
+                y2: MAP_HEIGHT - 1,
+            },
+            rng,
+            &mut leaves,
+        );
+
+        // one room of random size somewhere in every leaf. Leaves come out of the split in order,
+        // so each room is joined to a neighbour
+        let mut rooms: Vec<Room> = vec![];
+        for leaf in leaves {
+            let w = rng.gen_range(
+                ROOM_MIN_SIZE,
+                cmp::min(leaf.x2 - leaf.x1, ROOM_MAX_SIZE) + 1,
+            );
+            let h = rng.gen_range(
+                ROOM_MIN_SIZE,
+                cmp::min(leaf.y2 - leaf.y1, ROOM_MAX_SIZE) + 1,
+            );
+            let x = rng.gen_range(leaf.x1, leaf.x2 - w + 1);
+            let y = rng.gen_range(leaf.y1, leaf.y2 - h + 1);
+            let room = Room::new(x, y, w, h);
+            create_room(room, &mut map);
+            if let Some(prev_room) = rooms.last() {
+                connect_rooms(*prev_room, room, &mut map, rng);
+            }
+            rooms.push(room);
+        }
+        rooms_map(map, rooms, rng)
+    }
+}
+
+impl MapBuilder for CaveBuilder {
+    fn build(&self, rng: &mut GameRng) -> BuiltMap {
+        let mut map = new_map();
+        for x in 1..MAP_WIDTH - 1 {
+            for y in 1..MAP_HEIGHT - 1 {
+                if rng.gen_range(0, 100) >= CAVE_WALL_CHANCE {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+
+        // a tile becomes wall when most of its neighbours are, and floor when most are not
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            let before = map.clone();
+            for x in 1..MAP_WIDTH - 1 {
+                for y in 1..MAP_HEIGHT - 1 {
+                    let walls = (-1..=1)
+                        .flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+                        .filter(|&pos| pos != (x, y))
+                        .filter(|&(x, y)| before[x as usize][y as usize].blocked)
+                        .count();
+                    if walls > 4 {
+                        map[x as usize][y as usize] = Tile::wall();
+                    } else if walls < 4 {
+                        map[x as usize][y as usize] = Tile::empty();
+                    }
+                }
+            }
+        }
+
+        // only the biggest cave is kept, the rest are filled in
+        let mut caves: Vec<Vec<(i32, i32)>> = vec![];
+        let mut seen = HashSet::new();
+        for x in 1..MAP_WIDTH - 1 {
+            for y in 1..MAP_HEIGHT - 1 {
+                if !map[x as usize][y as usize].blocked && !seen.contains(&(x, y)) {
+                    let cave = flood_fill(&map, (x, y), Tile::is_passable);
+                    seen.extend(cave.iter().copied());
+                    caves.push(cave);
+                }
+            }
+        }
+        let biggest = caves
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, cave)| cave.len())
+            .map_or(0, |(index, _)| index);
+        for (index, cave) in caves.iter().enumerate() {
+            if index != biggest {
+                for &(x, y) in cave {
+                    map[x as usize][y as usize] = Tile::wall();
+                }
+            }
+        }
+        if caves.is_empty() {
+            // the noise filled the whole map, leave a single floor tile to start on
+            map[(MAP_WIDTH / 2) as usize][(MAP_HEIGHT / 2) as usize] = Tile::empty();
+        }
+        open_map(map, rng)
+    }
+}
+
+impl MapBuilder for DrunkardBuilder {
+    fn build(&self, rng: &mut GameRng) -> BuiltMap {
+        let mut map = new_map();
+        let target = ((MAP_WIDTH - 2) * (MAP_HEIGHT - 2)) as usize * DRUNKARD_FLOOR_PERCENT / 100;
+        let mut floor = vec![(MAP_WIDTH / 2, MAP_HEIGHT / 2)];
+        map[(MAP_WIDTH / 2) as usize][(MAP_HEIGHT / 2) as usize] = Tile::empty();
+
+        // every walker starts from a tile already dug, so the whole map stays connected
+        while floor.len() < target {
+            let (mut x, mut y) = floor[rng.gen_range(0, floor.len())];
+            for _ in 0..DRUNKARD_STEPS {
+                let (dx, dy) = [(0, -1), (0, 1), (-1, 0), (1, 0)][rng.gen_range(0, 4)];
+                if !in_bounds(x + dx, y + dy) {
+                    continue;
+                }
+                x += dx;
+                y += dy;
+                if map[x as usize][y as usize].blocked {
+                    map[x as usize][y as usize] = Tile::empty();
+                    floor.push((x, y));
+                    if floor.len() >= target {
+                        break;
+                    }
+                }
+            }
+        }
+        open_map(map, rng)
+    }
+}
+
+/// Returns a map of solid wall with the perimeter around its edge
+///
+fn new_map() -> Map {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for x in 0..MAP_WIDTH {
+        map[x as usize][0] = Tile::perimeter();
+        map[x as usize][(MAP_HEIGHT - 1) as usize] = Tile::perimeter();
+    }
+    for y in 0..MAP_HEIGHT {
+        map[0][y as usize] = Tile::perimeter();
+        map[(MAP_WIDTH - 1) as usize][y as usize] = Tile::perimeter();
+    }
+    map
+}
+
+/// Returns true if the tile is inside the perimeter, where builders may dig
+///
+pub fn in_bounds(x: i32, y: i32) -> bool {
+    x > 0 && y > 0 && x < MAP_WIDTH - 1 && y < MAP_HEIGHT - 1
+}
+
+/// Joins two rooms with an L-shaped corridor between their centers
+///
+fn connect_rooms(from: Room, to: Room, map: &mut Map, rng: &mut GameRng) {
+    let (prev_x, prev_y) = from.center();
+    let (new_x, new_y) = to.center();
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_horizontal_passage(prev_x, new_x, prev_y, map);
+        create_vertical_passage(prev_y, new_y, new_x, map);
+    } else {
+        // first move vertically, then horizontally
+        create_vertical_passage(prev_y, new_y, prev_x, map);
+        create_horizontal_passage(prev_x, new_x, new_y, map);
+    }
+}
+
+/// Splits a BSP leaf along its longer side at a random point until the pieces are small, and
+/// collects the pieces in order
+///
+fn split_leaf(leaf: Room, rng: &mut GameRng, leaves: &mut Vec<Room>) {
+    let (w, h) = (leaf.x2 - leaf.x1, leaf.y2 - leaf.y1);
+    if w >= 2 * BSP_MIN_LEAF && w >= h {
+        let split = rng.gen_range(leaf.x1 + BSP_MIN_LEAF, leaf.x2 - BSP_MIN_LEAF + 1);
+        split_leaf(Room { x2: split, ..leaf }, rng, leaves);
+        split_leaf(Room { x1: split, ..leaf }, rng, leaves);
+    } else if h >= 2 * BSP_MIN_LEAF {
+        let split = rng.gen_range(leaf.y1 + BSP_MIN_LEAF, leaf.y2 - BSP_MIN_LEAF + 1);
+        split_leaf(Room { y2: split, ..leaf }, rng, leaves);
+        split_leaf(Room { y1: split, ..leaf }, rng, leaves);
+    } else {
+        leaves.push(leaf);
+    }
+}
+
+/// Finishes a map made of rooms: the player starts in the center of a random room, and each
+/// room is a spawn region
+///
+fn rooms_map(map: Map, rooms: Vec<Room>, rng: &mut GameRng) -> BuiltMap {
+    let start = rooms[rng.gen_range(0, rooms.len())].center();
+    let spawn_regions = rooms
+        .iter()
+        .map(|room| {
+            ((room.x1 + 1)..room.x2)
+                .flat_map(|x| ((room.y1 + 1)..room.y2).map(move |y| (x, y)))
+                .collect()
+        })
+        .collect();
+    BuiltMap {
+        map,
+        rooms,
+        start,
+        spawn_regions,
+    }
+}
+
+/// Finishes a map without rooms: the floor is cut into spawn regions, and the player starts in
+/// the middle of a random one
+///
+fn open_map(map: Map, rng: &mut GameRng) -> BuiltMap {
+    let mut spawn_regions: Vec<Vec<(i32, i32)>> = vec![];
+    for region_x in (0..MAP_WIDTH).step_by(REGION_WIDTH as usize) {
+        for region_y in (0..MAP_HEIGHT).step_by(REGION_HEIGHT as usize) {
+            let region: Vec<(i32, i32)> = (region_x..cmp::min(region_x + REGION_WIDTH, MAP_WIDTH))
+                .flat_map(|x| {
+                    (region_y..cmp::min(region_y + REGION_HEIGHT, MAP_HEIGHT)).map(move |y| (x, y))
+                })
+                .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+                .collect();
+            if region.len() >= MIN_REGION_TILES {
+                spawn_regions.push(region);
+            }
+        }
+    }
+    if spawn_regions.is_empty() {
+        // too little floor to cut up, all of it is one region
+        spawn_regions.push(floor_tiles(&map));
+    }
+    let start = region_center(&spawn_regions[rng.gen_range(0, spawn_regions.len())]);
+    BuiltMap {
+        map,
+        rooms: vec![],
+        start,
+        spawn_regions,
+    }
+}
+
+/// Returns every floor tile of the map
+///
+fn floor_tiles(map: &Map) -> Vec<(i32, i32)> {
+    (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| !map[x as usize][y as usize].blocked)
+        .collect()
+}
+
+/// Grows patches of grass on the floor of a map, around floor tiles picked at random
+///
+pub fn grow_grass(map: &mut Map, rng: &mut GameRng) {
+    let floor = floor_tiles(map);
+    if floor.is_empty() {
+        return;
+    }
+    for _ in 0..GRASS_PATCHES {
+        let (center_x, center_y) = floor[rng.gen_range(0, floor.len())];
+        for x in (center_x - GRASS_RADIUS)..=(center_x + GRASS_RADIUS) {
+            for y in (center_y - GRASS_RADIUS)..=(center_y + GRASS_RADIUS) {
+                let (dx, dy) = (x - center_x, y - center_y);
+                if !in_bounds(x, y)
+                    || dx.pow(2) + dy.pow(2) > GRASS_RADIUS.pow(2)
+                    || map[x as usize][y as usize].blocked
+                {
+                    continue;
+                }
+                if rng.gen_range(0, 100) < GRASS_CHANCE {
+                    map[x as usize][y as usize].grass = true;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the tiles that can be walked to from the start, going only through tiles that are
+/// passable
+///
+pub fn flood_fill(map: &Map, start: (i32, i32), passable: fn(&Tile) -> bool) -> Vec<(i32, i32)> {
+    let mut reached = vec![start];
+    let mut seen: HashSet<(i32, i32)> = reached.iter().copied().collect();
+    let mut next = 0;
+    while next < reached.len() {
+        let (x, y) = reached[next];
+        next += 1;
+        for &(dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0)] {
+            let pos = (x + dx, y + dy);
+            if in_map(pos.0, pos.1)
+                && passable(&map[pos.0 as usize][pos.1 as usize])
+                && seen.insert(pos)
+            {
+                reached.push(pos);
+            }
+        }
+    }
+    reached
+}
+
+/// Digs extra corridors until every floor tile can be walked to from the start. Each pocket of
+/// floor that is cut off is joined to the nearest floor the start reaches
+///
+pub fn join_cut_off_floor(map: &mut Map, start: (i32, i32), rng: &mut GameRng) {
+    let mut reached: HashSet<(i32, i32)> = flood_fill(map, start, Tile::is_passable)
+        .into_iter()
+        .collect();
+    for (x, y) in floor_tiles(map) {
+        if reached.contains(&(x, y)) {
+            continue;
+        }
+        // ties are broken by position so the same seed always digs the same corridors
+        let nearest = reached
+            .iter()
+            .copied()
+            .min_by_key(|&(to_x, to_y)| ((to_x - x).pow(2) + (to_y - y).pow(2), to_x, to_y));
+        let (to_x, to_y) = match nearest {
+            Some(nearest) => nearest,
+            None => return,
+        };
+        if rng.gen() {
+            create_horizontal_passage(x, to_x, y, map);
+            create_vertical_passage(y, to_y, to_x, map);
+        } else {
+            create_vertical_passage(y, to_y, x, map);
+            create_horizontal_passage(x, to_x, to_y, map);
+        }
+        reached.extend(flood_fill(map, (x, y), Tile::is_passable));
+    }
+}
+
+/// Checks that everything on a finished level can be walked to from the player's start: every
+/// floor tile and door, every object, and both pads of every portal. Locked doors count as open,
+/// as long as there are as many keys as locked doors to be found without going through one.
+/// Returns what can't be reached
+///
+pub fn validate_level(map: &Map, objects: &[Object]) -> Result<(), String> {
+    let start = objects[PLAYER].pos();
+    if map[start.0 as usize][start.1 as usize].blocked {
+        return Err(format!("the player starts inside a wall at {:?}", start));
+    }
+    let locked_doors = map
+        .iter()
+        .flatten()
+        .filter(|tile| matches!(tile.door, Some(door) if door.locked))
+        .count();
+    let before_locks: HashSet<(i32, i32)> = flood_fill(map, start, Tile::is_passable)
+        .into_iter()
+        .collect();
+    let keys: i32 = objects
+        .iter()
+        .filter(|object| object.item == Some(Item::Key) && before_locks.contains(&object.pos()))
+        .map(|object| object.count)
+        .sum();
+    if (keys as usize) < locked_doors {
+        return Err(format!(
+            "{} locked doors but only {} keys can be reached",
+            locked_doors, keys
+        ));
+    }
+
+    let reached: HashSet<(i32, i32)> =
+        flood_fill(map, start, |tile| !tile.blocked || tile.door.is_some())
+            .into_iter()
+            .collect();
+    if let Some(tile) = floor_tiles(map)
+        .into_iter()
+        .find(|tile| !reached.contains(tile))
+    {
+        return Err(format!("the floor at {:?} can't be reached", tile));
+    }
+    if let Some(object) = objects
+        .iter()
+        .find(|object| !reached.contains(&object.pos()))
+    {
+        return Err(format!(
+            "the {} at {:?} can't be reached",
+            object.name,
+            object.pos()
+        ));
+    }
+    for (x, y) in floor_tiles(map) {
+        if let Some(portal) = map[x as usize][y as usize].portal {
+            if !reached.contains(&portal.destination) {
+                return Err(format!(
+                    "the portal at {:?} leads to {:?}, which can't be reached",
+                    (x, y),
+                    portal.destination
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the tile of a region closest to its middle
+///
+pub fn region_center(region: &[(i32, i32)]) -> (i32, i32) {
+    let count = cmp::max(region.len(), 1) as i32;
+    let sum = region
+        .iter()
+        .fold((0, 0), |sum, &(x, y)| (sum.0 + x, sum.1 + y));
+    let middle = (sum.0 / count, sum.1 / count);
+    *region
+        .iter()
+        .min_by_key(|&&(x, y)| (x - middle.0).pow(2) + (y - middle.1).pow(2))
+        .unwrap_or(&middle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // How many seeds each map style is built with, and the levels built for each seed. Locked
+    // doors and traps only show up deeper down
+    const SEEDS: u64 = 40;
+    const LEVELS: [u32; 2] = [1, 6];
+
+    #[test]
+    fn every_builder_makes_connected_levels() {
+        let raws = load_raws().unwrap();
+        let mut player = Object::new(0, 0, '@', "player", WHITE, true);
+        player.alive = true;
+        let mut objects = vec![player];
+        for &style in MapStyle::ALL.iter() {
+            for seed in 0..SEEDS {
+                let mut rng = GameRng::new(seed);
+                for &level in LEVELS.iter() {
+                    let map = build_level(&mut objects, level, style, &raws, &mut rng);
+                    if let Err(e) = validate_level(&map, &objects) {
+                        panic!("{:?} level {} with seed {}: {}", style, level, seed, e);
+                    }
+                }
+            }
+        }
+    }
+}
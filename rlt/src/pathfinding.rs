@@ -0,0 +1,170 @@
+use super::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Cost of a straight and a diagonal step. Both take a turn, but a diagonal step costs a little
+// more so that routes do not zigzag
+const STRAIGHT_COST: i32 = 10;
+const DIAGONAL_COST: i32 = 14;
+
+// The eight tiles around a tile, straight steps first
+const STEPS: [(i32, i32); 8] = [
+    (0, -1),
+    (0, 1),
+    (-1, 0),
+    (1, 0),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+/// Finds the shortest route between two tiles with A*, avoiding tiles that are blocked on the map
+/// or by a blocking object. Closed doors that aren't locked can be opened, so routes go through
+/// them, and traps that monsters know about are stepped around. The goal may hold a blocking
+/// object, such as the player being chased. Returns the tiles to step on in order, not including
+/// the start, or None if the goal cannot be reached. With no objects it checks whether the map
+/// alone connects the two tiles
+///
+pub fn find_path(
+    from: (i32, i32),
+    to: (i32, i32),
+    map: &Map,
+    objects: &[Object],
+) -> Option<Vec<(i32, i32)>> {
+    if !in_map(to.0, to.1) || !map[to.0 as usize][to.1 as usize].is_passable() {
+        return None;
+    }
+    if from == to {
+        return Some(vec![]);
+    }
+
+    let index = |(x, y): (i32, i32)| (x * MAP_HEIGHT + y) as usize;
+    let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+    let mut blocked = vec![false; size];
+    for object in objects {
+        if object.blocks && object.pos() != to && in_map(object.x, object.y) {
+            blocked[index(object.pos())] = true;
+        }
+    }
+
+    // cost of the cheapest known route to each tile, and the tile it came from
+    let mut costs = vec![i32::MAX; size];
+    let mut came_from = vec![None; size];
+    let mut open = BinaryHeap::new();
+    costs[index(from)] = 0;
+    open.push(Reverse((estimate(from, to), 0, from)));
+
+    while let Some(Reverse((_, cost, tile))) = open.pop() {
+        if tile == to {
+            // walk back from the goal to build the route
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(previous) = came_from[index(current)] {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // skip tiles that were reached more cheaply since they were queued
+        if cost > costs[index(tile)] {
+            continue;
+        }
+        for &(dx, dy) in STEPS.iter() {
+            let next = (tile.0 + dx, tile.1 + dy);
+            if !in_map(next.0, next.1)
+                || !map[next.0 as usize][next.1 as usize].is_passable()
+                || blocked[index(next)]
+                || (next != to && known_trap(map, next))
+            {
+                continue;
+            }
+            let step_cost = if dx != 0 && dy != 0 {
+                DIAGONAL_COST
+            } else {
+                STRAIGHT_COST
+            };
+            let next_cost = cost + step_cost;
+            if next_cost < costs[index(next)] {
+                costs[index(next)] = next_cost;
+                came_from[index(next)] = Some(tile);
+                open.push(Reverse((next_cost + estimate(next, to), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the cost of the cheapest route between two tiles if nothing was in the way
+///
+fn estimate(from: (i32, i32), to: (i32, i32)) -> i32 {
+    let dx = (to.0 - from.0).abs();
+    let dy = (to.1 - from.1).abs();
+    STRAIGHT_COST * (dx + dy) + (DIAGONAL_COST - 2 * STRAIGHT_COST) * cmp::min(dx, dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a map of walls with a single room with its corners at (1, 1) and (9, 5), split by a
+    /// wall down x = 5
+    fn split_room() -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for column in map.iter_mut().take(10).skip(1) {
+            column[1..=5].fill(Tile::empty());
+        }
+        map[5][1..=5].fill(Tile::wall());
+        map
+    }
+
+    /// Puts a closed door into the wall at (5, 3)
+    fn add_door(map: &mut Map, locked: bool) {
+        map[5][3] = Tile::door(locked);
+    }
+
+    /// Returns true if every step of the path goes to a tile next to the one before it
+    fn is_connected(from: (i32, i32), path: &[(i32, i32)]) -> bool {
+        let mut previous = from;
+        path.iter().all(|&(x, y)| {
+            let next_to = (x - previous.0).abs() <= 1 && (y - previous.1).abs() <= 1;
+            previous = (x, y);
+            next_to
+        })
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let mut map = split_room();
+        map[5][5] = Tile::empty();
+        let path = find_path((3, 1), (7, 1), &map, &[]).unwrap();
+        assert!(is_connected((3, 1), &path));
+        assert_eq!(path.last(), Some(&(7, 1)));
+        assert!(path.contains(&(5, 5)));
+        assert!(path
+            .iter()
+            .all(|&(x, y)| !map[x as usize][y as usize].blocked));
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let map = split_room();
+        assert_eq!(find_path((3, 1), (7, 1), &map, &[]), None);
+        assert_eq!(find_path((3, 1), (0, 0), &map, &[]), None);
+    }
+
+    #[test]
+    fn path_goes_through_closed_doors_but_not_locked_ones() {
+        let mut map = split_room();
+        add_door(&mut map, false);
+        let path = find_path((3, 3), (7, 3), &map, &[]).unwrap();
+        assert_eq!(path, vec![(4, 3), (5, 3), (6, 3), (7, 3)]);
+
+        add_door(&mut map, true);
+        assert_eq!(find_path((3, 3), (7, 3), &map, &[]), None);
+    }
+}
@@ -0,0 +1,138 @@
+use super::*;
+
+// After each trip the pads of a permanent pair close for this many turns
+const PORTAL_COOLDOWN: i32 = 10;
+
+// One of a linked pair of portal pads. Whoever stands on an open pad is taken to a free tile next
+// to the pad it links to. After a trip both pads close for cooldown turns, or vanish if the pair
+// is one-shot
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Portal {
+    pub destination: (i32, i32),
+    pub cooldown: i32,
+    pub closed_turns: i32,
+    pub one_shot: bool,
+}
+
+impl Portal {
+    pub fn is_open(&self) -> bool {
+        self.closed_turns == 0
+    }
+}
+
+/// Links two tiles with a pair of portal pads
+///
+pub fn place_portal_pair(
+    map: &mut Map,
+    a: (i32, i32),
+    b: (i32, i32),
+    cooldown: i32,
+    one_shot: bool,
+) {
+    for &(from, to) in &[(a, b), (b, a)] {
+        map[from.0 as usize][from.1 as usize].portal = Some(Portal {
+            destination: to,
+            cooldown,
+            closed_turns: 0,
+            one_shot,
+        });
+    }
+}
+
+/// Places the portals of a new level on the given spots: a permanent pair with a cooldown and a
+/// one-shot pair, as far as there are spots for them. Both pads of a pair have to be reachable on
+/// foot from the start, so a portal never leads into a sealed-off pocket
+///
+pub fn place_portals(
+    map: &mut Map,
+    mut spots: Vec<(i32, i32)>,
+    start: (i32, i32),
+    rng: &mut GameRng,
+) {
+    for &(cooldown, one_shot) in &[(PORTAL_COOLDOWN, false), (0, true)] {
+        if spots.len() < 2 {
+            break;
+        }
+        let a = spots.swap_remove(rng.gen_range(0, spots.len()));
+        let b = spots.swap_remove(rng.gen_range(0, spots.len()));
+        if find_path(start, a, map, &[]).is_some() && find_path(start, b, map, &[]).is_some() {
+            place_portal_pair(map, a, b, cooldown, one_shot);
+        }
+    }
+}
+
+/// Returns the free tile next to a pad where someone coming through it lands, if there is one
+///
+fn landing_tile(game: &Game, objects: &[Object], (x, y): (i32, i32)) -> Option<(i32, i32)> {
+    (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+        .find(|&(x, y)| {
+            in_map(x, y)
+                && game.map[x as usize][y as usize].portal.is_none()
+                && !game.map[x as usize][y as usize].stairs
+                && !is_blocked(x, y, &game.map, objects)
+        })
+}
+
+/// Takes the player and monsters standing on open portal pads through to the other pad of the
+/// pair, then closes or removes both pads
+///
+pub fn use_portals(game: &mut Game, objects: &mut [Object]) {
+    for id in 0..objects.len() {
+        if !objects[id].alive || objects[id].fighter.is_none() {
+            continue;
+        }
+        let (x, y) = objects[id].pos();
+        let portal = match game.map[x as usize][y as usize].portal {
+            Some(portal) if portal.is_open() => portal,
+            _ => continue,
+        };
+        let landing = match landing_tile(game, objects, portal.destination) {
+            Some(landing) => landing,
+            None => {
+                if id == PLAYER {
+                    game.messages
+                        .add("The portal flickers, the other side is blocked", LIGHT_BLUE);
+                }
+                continue;
+            }
+        };
+
+        let seen = game.fov.is_in_fov(x, y) || game.fov.is_in_fov(landing.0, landing.1);
+        if id == PLAYER {
+            game.messages.add("You step through the portal", LIGHT_BLUE);
+        } else if seen {
+            game.messages.add(
+                format!("The {} steps through a portal", objects[id].name),
+                LIGHT_BLUE,
+            );
+        }
+        objects[id].set_pos(landing.0, landing.1);
+
+        for &(x, y) in &[(x, y), portal.destination] {
+            let tile = &mut game.map[x as usize][y as usize];
+            if portal.one_shot {
+                tile.portal = None;
+            } else if let Some(pad) = tile.portal.as_mut() {
+                pad.closed_turns = pad.cooldown;
+            }
+        }
+        if portal.one_shot && (id == PLAYER || seen) {
+            game.messages.add("The portal collapses", LIGHT_BLUE);
+        }
+    }
+}
+
+/// Counts down the turns until closed portals open again
+///
+pub fn tick_portals(map: &mut Map) {
+    for portal in map
+        .iter_mut()
+        .flatten()
+        .filter_map(|tile| tile.portal.as_mut())
+    {
+        if portal.closed_turns > 0 {
+            portal.closed_turns -= 1;
+        }
+    }
+}
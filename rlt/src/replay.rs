@@ -0,0 +1,294 @@
+use super::*;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+// Replay file format version. Bump it whenever Recorded or Command changes shape
+const REPLAY_VERSION: u32 = 3;
+
+// The first line of a replay file. A replay only reproduces the game if RAWS_FILE is unchanged
+#[derive(Serialize, Deserialize)]
+struct ReplayHeader {
+    version: u32,
+    seed: u64,
+    map_style: Option<MapStyle>,
+}
+
+// Everything the player told the game, in the order it was asked for. Menu choices and targets
+// are recorded as well as commands so that items and level-ups replay the same way
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Recorded {
+    Command(Command),
+    Menu(Option<usize>),
+    Target(Option<(i32, i32)>),
+}
+
+// Wraps a frontend and writes every input it gives to a replay file. Each input is written as
+// soon as it is made, so the file is complete up to the last turn even if the game crashes
+pub struct Recorder<'a> {
+    frontend: &'a mut dyn Frontend,
+    file: Option<File>,
+}
+
+impl<'a> Recorder<'a> {
+    /// Creates the replay file for a new game, which starts with the game's seed and map style
+    pub fn create(
+        path: &str,
+        game: &Game,
+        frontend: &'a mut dyn Frontend,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::create(path)?;
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            seed: game.seed,
+            map_style: game.map_style,
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(Recorder {
+            frontend,
+            file: Some(file),
+        })
+    }
+
+    /// Appends an input to the replay file. Recording stops if the file can no longer be written
+    fn record(&mut self, input: Recorded) {
+        if let Some(file) = self.file.as_mut() {
+            let written = serde_json::to_string(&input)
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|line| Ok(writeln!(file, "{}", line)?));
+            if let Err(e) = written {
+                eprintln!("rlt: stopped recording the replay: {}", e);
+                self.file = None;
+            }
+        }
+    }
+}
+
+impl<'a> Renderer for Recorder<'a> {
+    fn render(&mut self, game: &Game, objects: &[Object]) {
+        self.frontend.render(game, objects);
+    }
+
+    fn clear(&mut self) {
+        self.frontend.clear();
+    }
+}
+
+impl<'a> Input for Recorder<'a> {
+    fn next_command(&mut self, game: &Game, objects: &[Object]) -> Option<Command> {
+        let command = self.frontend.next_command(game, objects);
+        if let Some(command) = command {
+            self.record(Recorded::Command(command));
+        }
+        command
+    }
+
+    fn menu(&mut self, header: &str, options: &[String], width: i32) -> Option<usize> {
+        let choice = self.frontend.menu(header, options, width);
+        self.record(Recorded::Menu(choice));
+        choice
+    }
+
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        start: (i32, i32),
+        max_range: f32,
+    ) -> Option<(i32, i32)> {
+        let target = self.frontend.target_tile(game, objects, start, max_range);
+        self.record(Recorded::Target(target));
+        target
+    }
+
+    fn closed(&self) -> bool {
+        self.frontend.closed()
+    }
+}
+
+// A recorded game, read back from a replay file
+pub struct Replay {
+    pub seed: u64,
+    pub map_style: Option<MapStyle>,
+    inputs: VecDeque<Recorded>,
+}
+
+impl Replay {
+    /// Reads a replay file written by Recorder
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = std::io::BufReader::new(File::open(path)?);
+        let mut lines = file.lines();
+        let header = lines.next().ok_or("the replay file is empty")??;
+        let header: ReplayHeader = serde_json::from_str(&header)?;
+        if header.version != REPLAY_VERSION {
+            return Err(format!(
+                "the replay file is version {}, this game plays version {}",
+                header.version, REPLAY_VERSION
+            )
+            .into());
+        }
+        let mut inputs = VecDeque::new();
+        for (number, line) in lines.enumerate() {
+            let input = serde_json::from_str(&line?)
+                .map_err(|e| format!("line {} of the replay file: {}", number + 2, e))?;
+            inputs.push_back(input);
+        }
+        Ok(Replay {
+            seed: header.seed,
+            map_style: header.map_style,
+            inputs,
+        })
+    }
+}
+
+// Plays a recorded game back turn by turn through a frontend, which only draws it. Turns are the
+// game's own turn count, so commands that don't take a turn, such as picking up or opening the
+// inventory, don't count. Playback stops once stop_at turns have been played, or when the
+// recording runs out
+pub struct ReplayPlayer<'a> {
+    frontend: &'a mut dyn Frontend,
+    inputs: VecDeque<Recorded>,
+    turn: u32,
+    stop_at: Option<usize>,
+    stopped: bool,
+}
+
+impl<'a> ReplayPlayer<'a> {
+    pub fn new(replay: Replay, stop_at: Option<usize>, frontend: &'a mut dyn Frontend) -> Self {
+        ReplayPlayer {
+            frontend,
+            inputs: replay.inputs,
+            turn: 0,
+            stop_at,
+            stopped: false,
+        }
+    }
+
+    /// Ends the playback, telling the player why and at which turn
+    fn stop(&mut self, reason: &str) {
+        self.stopped = true;
+        msgbox(
+            &format!(
+                "{} at turn {}.\n\nPress any key to exit.",
+                reason, self.turn
+            ),
+            INVENTORY_WIDTH,
+            self.frontend,
+        );
+    }
+}
+
+impl<'a> Renderer for ReplayPlayer<'a> {
+    fn render(&mut self, game: &Game, objects: &[Object]) {
+        self.frontend.render(game, objects);
+    }
+
+    fn clear(&mut self) {
+        self.frontend.clear();
+    }
+}
+
+impl<'a> Input for ReplayPlayer<'a> {
+    fn next_command(&mut self, game: &Game, _objects: &[Object]) -> Option<Command> {
+        self.turn = game.stats.turns;
+        if self.stopped || self.frontend.closed() {
+            return Some(Command::Exit);
+        }
+        if matches!(self.stop_at, Some(stop_at) if self.turn as usize >= stop_at) {
+            self.stop("Replay stopped");
+            return Some(Command::Exit);
+        }
+        match self.inputs.pop_front() {
+            // the player quitting is where the recording ends
+            Some(Recorded::Command(Command::Exit)) | None => {
+                self.stop("The replay ended");
+                Some(Command::Exit)
+            }
+            Some(Recorded::Command(command)) => Some(command),
+            Some(_) => {
+                self.stop("The replay is out of step with the game");
+                Some(Command::Exit)
+            }
+        }
+    }
+
+    fn menu(&mut self, _header: &str, _options: &[String], _width: i32) -> Option<usize> {
+        match self.inputs.pop_front() {
+            Some(Recorded::Menu(choice)) if !self.stopped => choice,
+            _ => {
+                if !self.stopped {
+                    self.stop("The replay is out of step with the game");
+                }
+                None
+            }
+        }
+    }
+
+    fn target_tile(
+        &mut self,
+        _game: &Game,
+        _objects: &[Object],
+        _start: (i32, i32),
+        _max_range: f32,
+    ) -> Option<(i32, i32)> {
+        match self.inputs.pop_front() {
+            Some(Recorded::Target(target)) if !self.stopped => target,
+            _ => {
+                if !self.stopped {
+                    self.stop("The replay is out of step with the game");
+                }
+                None
+            }
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.stopped || self.frontend.closed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    // Moves about, picks up and casts the starting Fire Ring Scroll, and searches for traps
+    const SCRIPT: &str = "right\ndown\npickup\nleft\nup\npickup\nuse a\n12 12\nsearch\n\
+                          right\nright\ndown\ndown\nleft\nup\nsearch\nquit\n";
+
+    /// Returns the player's position and HP, and every message of the game
+    fn outcome(game: &Game, objects: &[Object]) -> ((i32, i32), i32, Vec<String>) {
+        let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        let messages = game.messages.iter().map(|m| m.to_string()).collect();
+        (objects[PLAYER].pos(), hp, messages)
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_game() {
+        let path = std::env::temp_dir().join(format!("rlt-test-{}.replay", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let (mut game, mut objects) = new_game(load_raws().unwrap(), 9, Some(MapStyle::Rooms));
+        {
+            let mut headless = HeadlessFrontend::new(Cursor::new(SCRIPT), io::sink());
+            let mut recorder = Recorder::create(path, &game, &mut headless).unwrap();
+            play_game(&mut recorder, &mut game, &mut objects);
+        }
+
+        let replay = Replay::load(path).unwrap();
+        let (mut replayed, mut replayed_objects) =
+            new_game(load_raws().unwrap(), replay.seed, replay.map_style);
+        {
+            let mut headless = HeadlessFrontend::new(io::empty(), io::sink());
+            let mut player = ReplayPlayer::new(replay, None, &mut headless);
+            play_game(&mut player, &mut replayed, &mut replayed_objects);
+        }
+        let _ = fs::remove_file(path);
+
+        assert!(game.stats.turns > 0);
+        assert_eq!(replayed.stats.turns, game.stats.turns);
+        assert_eq!(
+            outcome(&replayed, &replayed_objects),
+            outcome(&game, &objects)
+        );
+    }
+}
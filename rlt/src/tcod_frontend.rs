@@ -0,0 +1,714 @@
+use super::Renderer;
+use super::*;
+use tcod::console::*;
+
+// The entire size of the window
+const SCREEN_WIDTH: i32 = 120;
+const SCREEN_HEIGHT: i32 = 80;
+
+// Panel and messaging interface
+const BAR_WIDTH: i32 = 20;
+const PANEL_HEIGHT: i32 = 7;
+const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+const MSG_X: i32 = BAR_WIDTH + 2;
+const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
+const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+
+// Where the player's status effects are listed, to the right of the map, and where the expected
+// damage against the monster under the targeting cursor is shown below them
+const STATUS_X: i32 = MAP_WIDTH + 2;
+const MATCHUP_Y: i32 = MAP_HEIGHT - 4;
+
+// FPS
+const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
+
+// RGB data for various Tile states
+const COLOR_DARK_WALL: Color = Color {
+    r: 120,
+    g: 120,
+    b: 120,
+};
+const COLOR_LIGHT_WALL: Color = Color {
+    r: 255,
+    g: 205,
+    b: 105,
+};
+const COLOR_LIGHT_PERIMETER: Color = Color {
+    r: 100,
+    g: 100,
+    b: 100,
+};
+const COLOR_DARK_PERIMETER: Color = Color {
+    r: 40,
+    g: 40,
+    b: 40,
+};
+const COLOR_DARK_GROUND: Color = Color {
+    r: 65,
+    g: 90,
+    b: 50,
+};
+const COLOR_LIGHT_TELEPORT: Color = Color { r: 0, g: 0, b: 225 };
+const COLOR_DARK_TELEPORT: Color = Color { r: 0, g: 0, b: 130 };
+const COLOR_DOOR: Color = Color {
+    r: 160,
+    g: 100,
+    b: 40,
+};
+const COLOR_GRASS: Color = Color {
+    r: 110,
+    g: 170,
+    b: 60,
+};
+const COLOR_LIGHT_STAIRS: Color = Color {
+    r: 150,
+    g: 110,
+    b: 60,
+};
+const COLOR_DARK_STAIRS: Color = Color {
+    r: 90,
+    g: 65,
+    b: 35,
+};
+const COLOR_SMOKE: Color = Color {
+    r: 150,
+    g: 150,
+    b: 160,
+};
+const COLOR_TARGET: Color = Color {
+    r: 255,
+    g: 255,
+    b: 150,
+};
+const COLOR_TARGET_OUT_OF_RANGE: Color = Color {
+    r: 110,
+    g: 110,
+    b: 110,
+};
+
+// The libtcod window frontend. The map is drawn to con and the player's stats and messages to
+// panel, both are then blitted to root
+pub struct TcodFrontend {
+    root: Root,
+    con: Offscreen,
+    panel: Offscreen,
+}
+
+impl TcodFrontend {
+    /// Opens the game window
+    pub fn new() -> Self {
+        tcod::system::set_fps(LIMIT_FPS);
+
+        // Root setup
+        let root = Root::initializer()
+            .font("arial10x10.png", FontLayout::Tcod)
+            .font_type(FontType::Greyscale)
+            .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+            .title("Rust/libtcod tutorial")
+            .init();
+
+        TcodFrontend {
+            root,
+            con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
+            panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+        }
+    }
+}
+
+impl Renderer for TcodFrontend {
+    fn render(&mut self, game: &Game, objects: &[Object]) {
+        render_all(self, game, objects);
+        self.root.flush();
+    }
+
+    fn clear(&mut self) {
+        self.root.set_default_background(BLACK);
+        self.root.clear();
+    }
+}
+
+impl Input for TcodFrontend {
+    /// Key controls for player movement and gameplay
+    fn next_command(&mut self, game: &Game, _objects: &[Object]) -> Option<Command> {
+        use tcod::input::Key;
+        use tcod::input::KeyCode::*;
+
+        let key = self.root.wait_for_keypress(true);
+        if self.root.window_closed() {
+            return Some(Command::Exit);
+        }
+        match (key, key.text()) {
+            (
+                Key {
+                    code: Enter,
+                    alt: true,
+                    ..
+                },
+                _,
+            ) => {
+                // Alt+Enter: toggle fullscreen
+                let fullscreen = self.root.is_fullscreen();
+                self.root.set_fullscreen(!fullscreen);
+                None
+            }
+            (Key { code: Escape, .. }, _) => Some(Command::Exit), // save and exit game
+
+            // movement keys
+            (Key { code: Up, .. }, _) => Some(Command::Move(0, -1)),
+            (Key { code: Down, .. }, _) => Some(Command::Move(0, 1)),
+            (Key { code: Left, .. }, _) => Some(Command::Move(-1, 0)),
+            (Key { code: Right, .. }, _) => Some(Command::Move(1, 0)),
+            (Key { code: Text, .. }, "g") => Some(Command::PickUp),
+            (Key { code: Text, .. }, ">") => Some(Command::Descend),
+            (Key { code: Text, .. }, "h") => {
+                show_history(&mut self.root, game);
+                None
+            }
+            (Key { code: Tab, .. }, _) => inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to use it, or any other to cancel.\n",
+                self,
+            )
+            .map(Command::UseItem),
+            (Key { code: Text, .. }, "c") => {
+                // the next arrow key picks the door to close
+                let key = self.root.wait_for_keypress(true);
+                match key.code {
+                    Up => Some(Command::CloseDoor(0, -1)),
+                    Down => Some(Command::CloseDoor(0, 1)),
+                    Left => Some(Command::CloseDoor(-1, 0)),
+                    Right => Some(Command::CloseDoor(1, 0)),
+                    _ => None,
+                }
+            }
+            (Key { code: Text, .. }, "s") => Some(Command::Search),
+            (Key { code: Text, .. }, "x") => {
+                // the next arrow key picks the trap to disarm
+                let key = self.root.wait_for_keypress(true);
+                match key.code {
+                    Up => Some(Command::DisarmTrap(0, -1)),
+                    Down => Some(Command::DisarmTrap(0, 1)),
+                    Left => Some(Command::DisarmTrap(-1, 0)),
+                    Right => Some(Command::DisarmTrap(1, 0)),
+                    _ => None,
+                }
+            }
+            (Key { code: Text, .. }, "d") => inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to drop it, or any other to cancel.\n",
+                self,
+            )
+            .map(Command::DropItem),
+            (Key { code: Text, .. }, "t") => inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to throw it, or any other to cancel.\n",
+                self,
+            )
+            .map(Command::ThrowItem),
+            _ => None,
+        }
+    }
+
+    fn menu(&mut self, header: &str, options: &[String], width: i32) -> Option<usize> {
+        menu(header, options, width, &mut self.root)
+    }
+
+    /// Lets the player pick a tile with a cursor, starting on the start tile. The cursor is moved
+    /// with the arrow keys or the mouse. Enter or a left-click confirms a tile in the player's FOV
+    /// within max_range of the player, Esc or a right-click cancels and returns None
+    fn target_tile(
+        &mut self,
+        game: &Game,
+        objects: &[Object],
+        start: (i32, i32),
+        max_range: f32,
+    ) -> Option<(i32, i32)> {
+        use tcod::input::{self, Event, KeyCode::*};
+
+        let in_range = |(x, y): (i32, i32)| {
+            game.fov.is_in_fov(x, y) && objects[PLAYER].distance(x, y) <= max_range
+        };
+        let mut cursor = start;
+        let mut redraw = true;
+        while !self.root.window_closed() {
+            // only redraw when the cursor moved, the game state is frozen while targeting
+            if redraw {
+                render_all(self, game, objects);
+                let color = if in_range(cursor) {
+                    COLOR_TARGET
+                } else {
+                    COLOR_TARGET_OUT_OF_RANGE
+                };
+                self.root
+                    .set_char_background(cursor.0, cursor.1, color, BackgroundFlag::Set);
+                render_matchup(&mut self.root, game, objects, cursor);
+                redraw = false;
+            }
+            self.root.flush();
+
+            let (dx, dy) = match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+                Some((_, Event::Mouse(mouse))) => {
+                    if mouse.rbutton_pressed {
+                        return None;
+                    }
+                    let pos = (mouse.cx as i32, mouse.cy as i32);
+                    if pos != cursor && in_map(pos.0, pos.1) {
+                        cursor = pos;
+                        redraw = true;
+                    }
+                    if mouse.lbutton_pressed && in_range(cursor) {
+                        return Some(cursor);
+                    }
+                    continue;
+                }
+                Some((_, Event::Key(key))) => match key.code {
+                    Escape => return None,
+                    Enter | NumPadEnter if in_range(cursor) => return Some(cursor),
+                    Up => (0, -1),
+                    Down => (0, 1),
+                    Left => (-1, 0),
+                    Right => (1, 0),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let (x, y) = (cursor.0 + dx, cursor.1 + dy);
+            if in_map(x, y) {
+                cursor = (x, y);
+                redraw = true;
+            }
+        }
+        None
+    }
+
+    fn closed(&self) -> bool {
+        self.root.window_closed()
+    }
+}
+
+/// Shows how much damage the player and the monster under the cursor do to each other on
+/// average, if there is a monster there that the player can see
+///
+fn render_matchup(root: &mut Root, game: &Game, objects: &[Object], (x, y): (i32, i32)) {
+    let monster = objects.iter().find(|object| {
+        object.pos() == (x, y) && object.alive && object.fighter.is_some() && !object.is_player()
+    });
+    let monster = match monster {
+        Some(monster) if game.fov.is_in_fov(x, y) => monster,
+        _ => return,
+    };
+    let player = &objects[PLAYER];
+    let lines = [
+        monster.name.clone(),
+        format!("You hit for {:.1}", player.expected_damage(monster, game)),
+        format!("It hits for {:.1}", monster.expected_damage(player, game)),
+    ];
+    root.set_default_foreground(WHITE);
+    for (row, line) in lines.iter().enumerate() {
+        root.print(STATUS_X, MATCHUP_Y + row as i32, line);
+    }
+}
+
+/// main function to render the game state, objects in FOV, and map
+fn render_all(tcod: &mut TcodFrontend, game: &Game, objects: &[Object]) {
+    tcod.root.clear();
+    tcod.con.clear();
+
+    // Set Tile background colors with pattern matching
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let visible = game.fov.is_in_fov(x, y);
+            let tile = &game.map[x as usize][y as usize];
+            let wall = tile.block_sight;
+            let perimeter = tile.perimeter;
+            let teleport = tile.portal.is_some();
+            let stairs = tile.stairs;
+            let on_fire = tile.fire > 0;
+
+            let color = match (visible, wall, perimeter, teleport, stairs, on_fire) {
+                // Outside player's FOV
+                (false, true, true, false, false, false) => COLOR_DARK_PERIMETER,
+                (false, true, false, false, false, false) => COLOR_DARK_WALL,
+                (false, false, false, true, false, false) => COLOR_DARK_TELEPORT,
+                (false, false, false, false, true, false) => COLOR_DARK_STAIRS,
+                (false, false, false, false, false, false) => COLOR_DARK_GROUND,
+                // Inside player's FOV
+                (true, true, true, false, false, false) => COLOR_LIGHT_PERIMETER,
+                (true, true, false, false, false, false) => COLOR_LIGHT_WALL,
+                (true, false, false, true, false, false) => COLOR_LIGHT_TELEPORT,
+                (true, false, false, false, true, false) => COLOR_LIGHT_STAIRS,
+                (true, false, false, false, false, false) => COLOR_DARK_GROUND,
+                (_, _, _, _, _, true) => LIGHTER_RED,
+                _ => COLOR_DARK_PERIMETER,
+            };
+            // A closed portal is dimmed until it opens again
+            let color = match tile.portal {
+                Some(portal) if !portal.is_open() && !on_fire => COLOR_DARK_TELEPORT,
+                _ => color,
+            };
+            // Smoke hides the tile under it
+            let color = if tile.smoke > 0 && !on_fire {
+                COLOR_SMOKE
+            } else {
+                color
+            };
+
+            // Only explored tiles are drawn
+            if tile.explored {
+                tcod.con
+                    .set_char_background(x, y, color, BackgroundFlag::Set);
+                // Grass is a '"' on the ground until it burns away
+                if tile.grass {
+                    tcod.con.set_default_foreground(COLOR_GRASS);
+                    tcod.con.put_char(x, y, '"', BackgroundFlag::None);
+                }
+                // Stairs are marked with a '>' so they can be told apart from the ground
+                if stairs {
+                    tcod.con.set_default_foreground(WHITE);
+                    tcod.con.put_char(x, y, '>', BackgroundFlag::None);
+                }
+                // Doors are a '+' while closed, gold if locked, and a '/' while open
+                if let Some(door) = tile.door {
+                    let (glyph, color) = match (door.open, door.locked) {
+                        (true, _) => ('/', COLOR_DOOR),
+                        (false, false) => ('+', COLOR_DOOR),
+                        (false, true) => ('+', GOLD),
+                    };
+                    tcod.con.set_default_foreground(color);
+                    tcod.con.put_char(x, y, glyph, BackgroundFlag::None);
+                }
+                // Traps the player has found are a '^' in the color of their kind
+                if let Some(trap) = tile.trap.filter(|trap| trap.found) {
+                    tcod.con.set_default_foreground(trap.kind.color());
+                    tcod.con.put_char(x, y, '^', BackgroundFlag::None);
+                }
+            }
+        }
+    }
+    let mut to_draw: Vec<_> = objects
+        .iter()
+        .filter(|o| game.fov.is_in_fov(o.x, o.y))
+        .collect();
+    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    for object in &to_draw {
+        // Draws the object with the correct color and coordinates
+        tcod.con.set_default_foreground(object.color);
+        tcod.con
+            .put_char(object.x, object.y, object.char, BackgroundFlag::None);
+    }
+
+    // blit is a special tcod function to push the contents of "con" to the root console
+    blit(
+        &tcod.con,
+        (0, 0),
+        (MAP_WIDTH, MAP_HEIGHT),
+        &mut tcod.root,
+        (0, 0),
+        1.0,
+        1.0,
+    );
+
+    // List the player's status effects to the right of the map, and whether they are burdened
+    let mut status: Vec<(String, Color)> = objects[PLAYER]
+        .effects
+        .iter()
+        .map(|effect| {
+            (
+                format!("{} ({} turns)", effect.kind, effect.turns),
+                effect.kind.color(),
+            )
+        })
+        .collect();
+    if is_burdened(&game.inventory) {
+        status.push(("burdened".into(), ORANGE));
+    }
+    if !status.is_empty() {
+        tcod.root.set_default_foreground(WHITE);
+        tcod.root.print(STATUS_X, 1, "Status:");
+    }
+    for (y, (text, color)) in status.into_iter().enumerate() {
+        tcod.root.set_default_foreground(color);
+        tcod.root.print(STATUS_X, y as i32 + 2, text);
+    }
+
+    tcod.panel.set_default_background(BLACK);
+    tcod.panel.clear();
+
+    // Print messages to UI panel
+    let mut y = MSG_HEIGHT as i32;
+    for message in game.messages.iter().rev() {
+        let msg = message.to_string();
+        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, &msg);
+        y -= msg_height;
+        if y < 0 {
+            break;
+        }
+        tcod.panel.set_default_foreground(message.color);
+        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+    }
+
+    // Render how hungry the player is above the health meter
+    let hunger = game.hunger();
+    tcod.panel.set_default_foreground(hunger.color());
+    tcod.panel.print_ex(
+        1,
+        0,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Hunger: {}", hunger),
+    );
+
+    // Render the player's attributes (health meter)
+    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let max_hp = objects[PLAYER].max_hp(game);
+    render_bar(
+        &mut tcod.panel,
+        1,
+        1,
+        BAR_WIDTH,
+        "HP",
+        hp,
+        max_hp,
+        LIGHT_RED,
+        DARKER_RED,
+    );
+
+    // Render the player's experience towards the next level
+    let xp = objects[PLAYER].fighter.map_or(0, |f| f.xp);
+    render_bar(
+        &mut tcod.panel,
+        1,
+        2,
+        BAR_WIDTH,
+        "XP",
+        xp,
+        level_up_xp(objects[PLAYER].level),
+        LIGHT_BLUE,
+        DARKER_BLUE,
+    );
+
+    // Render how deep the player is in the dungeon and the player's level
+    tcod.panel.set_default_foreground(WHITE);
+    tcod.panel.print_ex(
+        1,
+        3,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Dungeon level: {}", game.dungeon_level),
+    );
+    tcod.panel.print_ex(
+        1,
+        4,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Player level: {}", objects[PLAYER].level),
+    );
+    tcod.panel.print_ex(
+        1,
+        5,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!(
+            "Attack: {} Defense: {}",
+            objects[PLAYER].power(game),
+            objects[PLAYER].defense(game)
+        ),
+    );
+    tcod.panel.print_ex(
+        1,
+        6,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Seed: {}", game.seed),
+    );
+
+    // Output the panel with blit
+    blit(
+        &tcod.panel,
+        (0, 0),
+        (SCREEN_WIDTH, PANEL_HEIGHT),
+        &mut tcod.root,
+        (0, PANEL_Y),
+        1.0,
+        1.0,
+    );
+}
+
+/// Shows the message history full-screen, newest at the bottom. Up/Down and PageUp/PageDown
+/// scroll, Home/End jump to the oldest and newest messages, "/" starts a search that only shows
+/// messages containing the typed text, and Esc ends the search or closes the history
+///
+fn show_history(root: &mut Root, game: &Game) {
+    use tcod::input::KeyCode::*;
+
+    let page = (SCREEN_HEIGHT - 2) as usize;
+    let mut scroll = 0; // lines scrolled up from the newest message
+    let mut search = String::new();
+    let mut typing = false;
+    while !root.window_closed() {
+        let query = search.to_lowercase();
+        let lines: Vec<(String, Color)> = game
+            .messages
+            .iter()
+            .map(|message| (message.to_string(), message.color))
+            .filter(|(text, _)| text.to_lowercase().contains(&query))
+            .collect();
+        let max_scroll = lines.len().saturating_sub(page);
+        scroll = cmp::min(scroll, max_scroll);
+        let end = lines.len() - scroll;
+        let start = end.saturating_sub(page);
+
+        root.set_default_background(BLACK);
+        root.clear();
+        root.set_default_foreground(WHITE);
+        let title = if typing {
+            format!("Search: {}_", search)
+        } else if !search.is_empty() {
+            format!(
+                "Messages containing \"{}\" ({} of {}), Esc to show all",
+                search,
+                end,
+                lines.len()
+            )
+        } else {
+            format!(
+                "Message history ({} of {}), arrows and PgUp/PgDn to scroll, / to search, Esc to \
+                 close",
+                end,
+                lines.len()
+            )
+        };
+        root.print(0, 0, title);
+        for (row, (text, color)) in lines[start..end].iter().enumerate() {
+            root.set_default_foreground(*color);
+            root.print_rect(0, row as i32 + 2, SCREEN_WIDTH, 1, text);
+        }
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        if typing {
+            match key.code {
+                Enter => typing = false,
+                Escape => {
+                    typing = false;
+                    search.clear();
+                }
+                Backspace => {
+                    search.pop();
+                }
+                Text => search.push_str(key.text()),
+                _ => {}
+            }
+            continue;
+        }
+        match (key.code, key.text()) {
+            (Up, _) => scroll += 1,
+            (Down, _) => scroll = scroll.saturating_sub(1),
+            (PageUp, _) => scroll += page,
+            (PageDown, _) => scroll = scroll.saturating_sub(page),
+            (Home, _) => scroll = max_scroll,
+            (End, _) => scroll = 0,
+            (Text, "/") => {
+                typing = true;
+                search.clear();
+                scroll = 0;
+            }
+            (Escape, _) if !search.is_empty() => search.clear(),
+            (Escape, _) => break,
+            _ => {}
+        }
+    }
+}
+
+fn render_bar(
+    panel: &mut Offscreen,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    // Compute bar width
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+
+    // Set the background
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+
+    // Set the foreground and print the text on top
+    panel.set_default_foreground(WHITE);
+    panel.print_ex(
+        x + total_width / 2,
+        y,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        &format!("{}: {}/{}", name, value, maximum),
+    );
+}
+
+/// tcod menu setup primarly reused from tcodlib menu tutorial
+fn menu(header: &str, options: &[String], width: i32, root: &mut Root) -> Option<usize> {
+    assert!(
+        options.len() <= MAX_MENU_OPTIONS,
+        "Cannot have a menu with more than {} options.",
+        MAX_MENU_OPTIONS
+    );
+
+    let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
+    let height = options.len() as i32 + header_height;
+
+    let mut window = Offscreen::new(width, height);
+
+    window.set_default_foreground(WHITE);
+    window.print_rect_ex(
+        0,
+        0,
+        width,
+        height,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        header,
+    );
+
+    // print all the options
+    for (index, option_text) in options.iter().enumerate() {
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text);
+        window.print_ex(
+            0,
+            header_height + index as i32,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        );
+    }
+
+    // blit the contents of "window" to the root console
+    let x = SCREEN_WIDTH / 2 - width / 2;
+    let y = SCREEN_HEIGHT / 2 - height / 2;
+    blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+
+    // present the root console to the player and wait for a key-press
+    root.flush();
+    let key = root.wait_for_keypress(true);
+
+    // convert the ASCII code to an index; if it corresponds to an option, return it
+    if key.printable.is_alphabetic() {
+        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+        if index < options.len() {
+            Some(index)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
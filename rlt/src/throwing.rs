@@ -0,0 +1,175 @@
+use super::combat::roll_attack;
+use super::*;
+
+// How far the player can throw an item, and how far around where it lands a thrown potion
+// splashes
+const THROW_RANGE: f32 = 8.0;
+const SPLASH_RADIUS: f32 = 1.0;
+
+/// Returns how hard the item hits whoever it is thrown at: a missile's damage, a weapon's attack
+/// bonus, and 1 for anything else
+///
+fn throw_damage(item: &Object) -> i32 {
+    match (item.item, item.equipment) {
+        (Some(Item::Missile { damage }), _) => damage,
+        (_, Some(equipment)) if equipment.slot == Slot::Weapon => {
+            cmp::max(equipment.power_bonus, 1)
+        }
+        _ => 1,
+    }
+}
+
+/// Returns true if the item shatters where it lands instead of falling to the floor
+///
+fn shatters(item: &Object) -> bool {
+    matches!(item.item, Some(Item::Heal { .. }) | Some(Item::Potion(_)))
+}
+
+/// Returns the tile a thrown item or shot lands on and the blocking object it hits there, if any.
+/// It flies along a straight line to the target, and stops at the first blocking object or in
+/// front of the first wall
+///
+pub fn flight(
+    from: (i32, i32),
+    to: (i32, i32),
+    map: &Map,
+    objects: &[Object],
+) -> ((i32, i32), Option<usize>) {
+    let mut landing = from;
+    for (x, y) in line(from, to) {
+        if !in_map(x, y) || map[x as usize][y as usize].blocked {
+            break;
+        }
+        landing = (x, y);
+        if let Some(id) = objects
+            .iter()
+            .position(|object| object.blocks && object.pos() == (x, y))
+        {
+            return (landing, Some(id));
+        }
+    }
+    (landing, None)
+}
+
+/// The player throws an item from the inventory at a tile they choose, one at a time from a
+/// stack. Potions shatter where they land and splash everyone next to it, anything else hurts the
+/// first monster in its way and lands on the floor, where it can be picked up again. Returns true
+/// if the item was thrown
+///
+pub fn throw_item(
+    inventory_id: usize,
+    frontend: &mut dyn Frontend,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+) -> bool {
+    // ask the player for a target, starting on the closest monster if there is one
+    game.messages.add(
+        format!(
+            "Choose where to throw the {} with the arrows or mouse, Enter or left-click to \
+             throw, Esc or right-click to cancel",
+            game.inventory[inventory_id].name
+        ),
+        LIGHT_CYAN,
+    );
+    let start = closest_monster(&game.fov, objects, THROW_RANGE)
+        .map_or(objects[PLAYER].pos(), |monster_id| {
+            objects[monster_id].pos()
+        });
+    let target = match frontend.target_tile(game, objects, start, THROW_RANGE) {
+        Some(target) => target,
+        None => {
+            game.messages.add("Cancelled", WHITE);
+            return false;
+        }
+    };
+
+    let mut item = take_one(inventory_id, game);
+    let (landing, hit) = flight(objects[PLAYER].pos(), target, &game.map, objects);
+    game.messages
+        .add(format!("You throw the {}", item.name), WHITE);
+    if shatters(&item) {
+        shatter(&item, landing, game, objects);
+        return true;
+    }
+    if let Some(target_id) = hit {
+        hit_with(&item, target_id, game, objects);
+    }
+    item.set_pos(landing.0, landing.1);
+    objects.push(item);
+    true
+}
+
+/// Takes a single item out of the inventory, off the top of its stack. Equipment is taken off
+/// first
+///
+fn take_one(inventory_id: usize, game: &mut Game) -> Object {
+    let was_burdened = is_burdened(&game.inventory);
+    let item = if game.inventory[inventory_id].count > 1 {
+        game.inventory[inventory_id].count -= 1;
+        let mut item = game.inventory[inventory_id].clone();
+        item.count = 1;
+        item
+    } else {
+        let mut item = game.inventory.remove(inventory_id);
+        item.dequip(&mut game.messages);
+        item
+    };
+    report_burden(was_burdened, game);
+    item
+}
+
+/// A thrown item hits a monster. It rolls to hit and for damage like an attack with the item's
+/// throwing damage as its power, and the player gets the experience if the monster dies
+///
+fn hit_with(item: &Object, target_id: usize, game: &mut Game, objects: &mut [Object]) {
+    let attack = roll_attack(
+        throw_damage(item),
+        objects[target_id].defense(game),
+        &mut game.rng,
+    );
+    game.messages.add(
+        format!(
+            "The {} flies at the {}: {}",
+            item.name, objects[target_id].name, attack
+        ),
+        if attack.critical { LIGHT_RED } else { WHITE },
+    );
+    if !attack.hit {
+        return;
+    }
+    let cause = format!("a thrown {}", item.name);
+    if let Some(xp) = objects[target_id].take_damage(attack.damage, &cause, game) {
+        if target_id != PLAYER {
+            objects[PLAYER].gain_xp(xp);
+        }
+    }
+}
+
+/// A thrown potion shatters, and every fighter within SPLASH_RADIUS of where it landed gets what
+/// drinking it would give. The player gets the experience for monsters its effect kills
+///
+fn shatter(potion: &Object, (x, y): (i32, i32), game: &mut Game, objects: &mut [Object]) {
+    game.messages
+        .add(format!("The {} shatters!", potion.name), LIGHT_VIOLET);
+    for object in objects
+        .iter_mut()
+        .filter(|object| object.alive && object.fighter.is_some())
+        .filter(|object| object.distance(x, y) <= SPLASH_RADIUS)
+    {
+        match potion.item {
+            Some(Item::Heal { amount }) => {
+                object.heal(amount, game);
+                game.messages
+                    .add(format!("The {} is healed", object.name), LIGHT_VIOLET);
+            }
+            Some(Item::Potion(effect)) => {
+                let effect = StatusEffect {
+                    from_player: true,
+                    ..effect
+                };
+                apply_effect(object, effect, game)
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use super::fire::set_tiles_on_fire;
+use super::*;
+
+// A level has TRAPS_BASE traps, plus one more for every TRAPS_PER_LEVELS levels down, up to
+// MAX_TRAPS. No trap is placed within SAFE_RADIUS of where the player starts
+const TRAPS_BASE: u32 = 2;
+const TRAPS_PER_LEVELS: u32 = 2;
+const MAX_TRAPS: u32 = 8;
+const SAFE_RADIUS: f32 = 3.0;
+
+// Each turn the player has SPOT_CHANCE out of 100 to spot each hidden trap in their FOV within
+// SPOT_RADIUS tiles. Searching takes a turn and finds each one within SEARCH_RADIUS with
+// SEARCH_CHANCE instead
+const SPOT_RADIUS: f32 = 3.0;
+const SPOT_CHANCE: u32 = 10;
+const SEARCH_RADIUS: f32 = 2.0;
+const SEARCH_CHANCE: u32 = 60;
+
+// The chance out of 100 that disarming a trap works, and that a failed attempt sets it off
+const DISARM_CHANCE: u32 = 60;
+const FUMBLE_CHANCE: u32 = 30;
+
+// What the traps do: darts and pits hurt whoever set them off, an alarm calls every monster
+// within ALARM_RADIUS over, and a fire trap sets the tiles within FIRE_TRAP_RANGE burning
+const DART_DAMAGE: i32 = 3;
+const PIT_DAMAGE: i32 = 5;
+const ALARM_RADIUS: f32 = 20.0;
+const FIRE_TRAP_RANGE: i32 = 1;
+
+// The kinds of trap
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    Dart,
+    Pit,
+    Alarm,
+    Fire,
+}
+
+// A trap hidden in a floor tile. It goes off whenever someone steps on it. found is set once the
+// player knows about it, by spotting it, searching or seeing it go off. Monsters only know about
+// a trap once it has gone off, and step around it from then on
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub found: bool,
+    pub sprung: bool,
+}
+
+impl TrapKind {
+    const ALL: [TrapKind; 4] = [
+        TrapKind::Dart,
+        TrapKind::Pit,
+        TrapKind::Alarm,
+        TrapKind::Fire,
+    ];
+
+    /// Returns the color a found trap is shown in
+    pub fn color(self) -> Color {
+        match self {
+            TrapKind::Dart => LIGHT_GREY,
+            TrapKind::Pit => DARK_ORANGE,
+            TrapKind::Alarm => LIGHT_YELLOW,
+            TrapKind::Fire => LIGHT_RED,
+        }
+    }
+}
+
+impl std::fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            TrapKind::Dart => write!(f, "dart trap"),
+            TrapKind::Pit => write!(f, "pit"),
+            TrapKind::Alarm => write!(f, "alarm trap"),
+            TrapKind::Fire => write!(f, "fire trap"),
+        }
+    }
+}
+
+/// Hides traps in free floor tiles of a new level, more of them the deeper the level
+///
+pub fn place_traps(map: &mut Map, objects: &[Object], level: u32, rng: &mut GameRng) {
+    let mut free: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| {
+            let tile = &map[x as usize][y as usize];
+            !tile.blocked
+                && tile.door.is_none()
+                && tile.portal.is_none()
+                && !tile.stairs
+                && objects[PLAYER].distance(x, y) > SAFE_RADIUS
+                && !objects.iter().any(|object| object.pos() == (x, y))
+        })
+        .collect();
+    let count = cmp::min(TRAPS_BASE + (level - 1) / TRAPS_PER_LEVELS, MAX_TRAPS);
+    for _ in 0..count {
+        if free.is_empty() {
+            break;
+        }
+        let (x, y) = free.swap_remove(rng.gen_range(0, free.len()));
+        map[x as usize][y as usize].trap = Some(Trap {
+            kind: TrapKind::ALL[rng.gen_range(0, TrapKind::ALL.len())],
+            found: false,
+            sprung: false,
+        });
+    }
+}
+
+/// Returns true if there is a trap on the tile that monsters know about and keep away from
+///
+pub fn known_trap(map: &Map, (x, y): (i32, i32)) -> bool {
+    in_map(x, y) && matches!(map[x as usize][y as usize].trap, Some(trap) if trap.sprung)
+}
+
+/// Sets off the trap under the object, if there is one. Everyone who sees it go off knows about
+/// it from then on
+///
+pub fn spring_trap(id: usize, game: &mut Game, objects: &mut [Object]) {
+    let (x, y) = objects[id].pos();
+    let trap = match game.map[x as usize][y as usize].trap.as_mut() {
+        Some(trap) => trap,
+        None => return,
+    };
+    let kind = trap.kind;
+    let seen = id == PLAYER || game.fov.is_in_fov(x, y);
+    trap.sprung = true;
+    trap.found = trap.found || seen;
+    if id == PLAYER {
+        game.messages
+            .add(format!("You set off a {}!", kind), ORANGE);
+    } else if seen {
+        game.messages.add(
+            format!("The {} sets off a {}!", objects[id].name, kind),
+            ORANGE,
+        );
+    }
+    trap_effect(kind, (x, y), id, game, objects);
+}
+
+/// Does what the trap does to its victim and the tiles and monsters around it
+///
+fn trap_effect(
+    kind: TrapKind,
+    pos: (i32, i32),
+    victim: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+) {
+    let cause = format!("a {}", kind);
+    match kind {
+        TrapKind::Dart | TrapKind::Pit => {
+            let damage = if kind == TrapKind::Dart {
+                DART_DAMAGE
+            } else {
+                PIT_DAMAGE
+            };
+            if victim == PLAYER || game.fov.is_in_fov(pos.0, pos.1) {
+                game.messages.add(
+                    format!(
+                        "The {} hurts {} for {} hit points",
+                        kind, objects[victim].name, damage
+                    ),
+                    ORANGE,
+                );
+            }
+            objects[victim].take_damage(damage, &cause, game);
+        }
+        TrapKind::Alarm => {
+            game.messages.add("A loud alarm rings out!", LIGHT_YELLOW);
+            for monster in objects.iter_mut().filter(|object| object.alive) {
+                let distance = monster.distance(pos.0, pos.1);
+                if let Some(perception) = monster.perception.as_mut() {
+                    // monsters that can see the player keep chasing them
+                    let sees_player =
+                        perception.last_seen.is_some() && perception.turns_unseen == 0;
+                    if distance <= ALARM_RADIUS && !sees_player {
+                        perception.last_seen = Some(pos);
+                        perception.turns_unseen = 1;
+                    }
+                }
+            }
+        }
+        TrapKind::Fire => set_tiles_on_fire(game, pos, FIRE_TRAP_RANGE, false),
+    }
+}
+
+/// Gives the player a chance to spot each hidden trap close to them that they can see. Searching
+/// looks closer, with a better chance. Returns true if a trap was found
+///
+pub fn spot_traps(game: &mut Game, player: &Object, searching: bool) -> bool {
+    let (radius, chance) = if searching {
+        (SEARCH_RADIUS, SEARCH_CHANCE)
+    } else {
+        (SPOT_RADIUS, SPOT_CHANCE)
+    };
+    let reach = radius as i32;
+    let mut spotted = false;
+    for x in (player.x - reach)..=(player.x + reach) {
+        for y in (player.y - reach)..=(player.y + reach) {
+            if !in_map(x, y) || player.distance(x, y) > radius || !game.fov.is_in_fov(x, y) {
+                continue;
+            }
+            let kind = match game.map[x as usize][y as usize].trap {
+                Some(trap) if !trap.found => trap.kind,
+                _ => continue,
+            };
+            if game.rng.gen_range(0, 100) >= chance {
+                continue;
+            }
+            if let Some(trap) = game.map[x as usize][y as usize].trap.as_mut() {
+                trap.found = true;
+            }
+            game.messages
+                .add(format!("You find a {}!", kind), LIGHT_CYAN);
+            spotted = true;
+        }
+    }
+    spotted
+}
+
+/// The player tries to disarm the trap they know about on the tile. It is removed if that works,
+/// and a failed attempt may set it off on the player. Returns false if there is no such trap
+///
+pub fn disarm_trap(game: &mut Game, objects: &mut [Object], (x, y): (i32, i32)) -> bool {
+    let kind = match game.map[x as usize][y as usize].trap {
+        Some(trap) if trap.found => trap.kind,
+        _ => {
+            game.messages
+                .add("There is no trap you know of there", WHITE);
+            return false;
+        }
+    };
+    if game.rng.gen_range(0, 100) < DISARM_CHANCE {
+        game.map[x as usize][y as usize].trap = None;
+        game.messages
+            .add(format!("You disarm the {}", kind), LIGHT_GREEN);
+    } else if game.rng.gen_range(0, 100) < FUMBLE_CHANCE {
+        game.messages
+            .add(format!("You fumble and set off the {}!", kind), ORANGE);
+        if let Some(trap) = game.map[x as usize][y as usize].trap.as_mut() {
+            trap.sprung = true;
+        }
+        trap_effect(kind, (x, y), PLAYER, game, objects);
+    } else {
+        game.messages
+            .add(format!("You fail to disarm the {}", kind), WHITE);
+    }
+    true
+}